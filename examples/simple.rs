@@ -1,46 +1,21 @@
 // clear && FLUTTER_RUST_EMBEDDER_LOG=trace cargo run --example simple
 // clear && cargo build
-// RUST_LOG=trace cargo run --example simple
 // target based filtering (default target is the module name)
-// RUST_LOG=flutter_rust_embedder=trace,wgpu=info cargo run --example simple
-// RUST_LOG=error cargo run --example simple
-// RUST_LOG=flutter_rust_embedder=trace,winit=error cargo run --example simple
-
-use flutter_rust_embedder::application::{AppError, GPUContext};
+// FLUTTER_RUST_EMBEDDER_LOG=flutter_rust_embedder=trace,wgpu=info cargo run --example simple
+// FLUTTER_RUST_EMBEDDER_LOG=error cargo run --example simple
+// record a Perfetto trace next to the executable, and the engine's timeline
+// with the embedder spans beside it
+// FLUTTER_RUST_EMBEDDER_PROFILE=debug cargo run --example simple
+
+use flutter_rust_embedder::application::{AppError, GPUContext, GPUContextOptions};
+use flutter_rust_embedder::surface::SurfaceOptions;
+use flutter_rust_embedder::tracing_integration::init_tracing;
 use tracing::{info, info_span};
-use tracing_perfetto::PerfettoLayer;
-use tracing_subscriber::fmt::format::Format;
-use tracing_subscriber::EnvFilter;
-use tracing_subscriber::{fmt, layer::SubscriberExt, Registry};
-
-fn init_subscriber() {
-    let exe_path = std::env::current_exe().unwrap();
-    let exe_dir = exe_path.parent().unwrap();
-    let time_str = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
-    let trace_path = exe_dir.join(format!("trace-{}.perfetto-trace", time_str));
-    let trace_file = std::fs::File::create(&trace_path).unwrap();
-    let perfetto_layer =
-        PerfettoLayer::new(std::sync::Mutex::new(trace_file)).with_debug_annotations(true);
-
-    // let fmt_layer = fmt::layer()
-    //     .with_writer(std::io::stdout)
-    //     .event_format(Format::default().with_thread_ids(true));
-    // // .with_span_events(fmt::format::FmtSpan::ACTIVE);
-
-    // let env_filter = EnvFilter::from_default_env();
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug"));
-    let subscriber = Registry::default()
-        // .with(fmt_layer)
-        .with(perfetto_layer)
-        .with(env_filter);
-
-    tracing::subscriber::set_global_default(subscriber).unwrap();
-}
 
 #[tokio::main(flavor = "current_thread")]
 
 async fn main() -> Result<(), AppError> {
-    init_subscriber();
+    let profile_path = init_tracing();
     let _span = info_span!("main").entered();
     info!("start app");
     // env_logger::init();
@@ -56,34 +31,21 @@ async fn main() -> Result<(), AppError> {
         flutter_engine_path: std::path::PathBuf::from(
             "C:/libs/flutter/engine/src/out/host_debug_unopt/flutter_engine.dll",
         ),
+        dart_entrypoint: None,
+        dart_entrypoint_args: Vec::new(),
+        locales: None,
+        system_settings: None,
+        vm_service_info_file: None,
+        watch_assets: false,
+        engine_trace_file: profile_path.map(|path| path.with_extension("engine.perfetto-trace")),
+        log_tag: None,
+        on_dart_log: None,
+        performance_overlay: false,
+        surface_options: SurfaceOptions::default(),
     };
 
-    let instance_desc = wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::VULKAN | wgpu::Backends::METAL,
-        flags: wgpu::InstanceFlags::default(),
-        backend_options: wgpu::BackendOptions::default(),
-    };
-    let instance = wgpu::Instance::new(&instance_desc);
-
-    info!("Surface created");
-
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions::default())
-        .await
-        .unwrap();
-    let (device, queue) = adapter
-        .request_device(&wgpu::DeviceDescriptor::default(), None)
-        .await
-        .unwrap();
+    let gpu_context = GPUContext::new_for_flutter(&GPUContextOptions::default())?;
 
-    let mut app = flutter_rust_embedder::application::App::new(
-        app_config,
-        GPUContext {
-            instance: instance,
-            adapter: adapter,
-            device: device,
-            queue: queue,
-        },
-    );
+    let mut app = flutter_rust_embedder::application::App::new(app_config, gpu_context);
     app.run()
 }
@@ -4,22 +4,48 @@ use std::ffi::{CStr, CString};
 use std::path::PathBuf;
 use std::pin::Pin;
 // use std::fmt::Error;
-use crate::composition::Compositor;
+use crate::asset_watcher::AssetWatcher;
+use crate::backing_store_pool::BackingStorePool;
+use crate::composition::{Compositor, ViewCompositors, IMPLICIT_VIEW_ID};
+use crate::displays::{self, Display, DEFAULT_REFRESH_RATE};
+use crate::engine_library::EngineLibrary;
+use crate::engine_log::{self, DartLogCallback};
+use crate::flutter_device_vk;
 use crate::flutter_embedder;
-use crate::utils::as_void_ptr;
+use crate::frame_stats::FrameStatsStream;
+use crate::input::{PointerButton, PointerState};
+use crate::json_codec::{self, JsonMethodCall, JsonValue};
+use crate::lifecycle::{
+    allows_exit, exit_response, AppLifecycleState, LIFECYCLE_CHANNEL, PLATFORM_CHANNEL,
+};
+use crate::locales::{
+    parse_locales, resolve_locale_callback, set_preferred_locales, system_locales,
+    with_flutter_locales,
+};
+use crate::platform_views::{PlatformViewRegistry, PLATFORM_VIEWS_CHANNEL};
+use crate::semantics::{AccessibilityAdapter, Semantics};
+use crate::settings::{OsSettings, SystemSettingsProvider, SETTINGS_CHANNEL};
+use crate::standard_codec::MethodCall;
+use crate::surface::SurfaceOptions;
+use crate::task_runner::PlatformTaskRunner;
+use crate::texture_registry::TextureRegistry;
+use crate::utils::{as_void_ptr, catch_callback_panic};
+use crate::vm_service::{self, VmService};
 use ash::vk::Handle;
 use chrono::Duration;
 use flutter_embedder::*;
-use libloading::Library;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use thiserror::Error;
 use tracing::{debug, debug_span, error, info, instrument, warn};
 use winit::platform::pump_events::{EventLoopExtPumpEvents, PumpStatus};
 // use wgpu::{Adapter, Instance};
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoop};
-use winit::window::{Window, WindowAttributes, WindowId};
+use winit::window::{Theme, Window, WindowAttributes, WindowId};
 
 pub type PinBox<T> = Pin<Box<T>>;
 
@@ -31,21 +57,190 @@ pub struct GPUContext {
     pub queue: wgpu::Queue,
 }
 
+/// What [`GPUContext::new_for_flutter`] asks of the adapter and device.
+#[derive(Clone, Debug)]
+pub struct GPUContextOptions {
+    /// Which kind of GPU to prefer when several meet the requirements.
+    pub power_preference: wgpu::PowerPreference,
+    /// Whether a software adapter such as lavapipe may be picked, as a last
+    /// resort or on machines without a GPU.
+    pub allow_software: bool,
+    /// Features the host renders with besides Flutter.
+    pub required_features: wgpu::Features,
+    pub required_limits: wgpu::Limits,
+    /// E.g. [`wgpu::InstanceFlags::VALIDATION`] to enable the validation layers.
+    pub instance_flags: wgpu::InstanceFlags,
+}
+
+impl Default for GPUContextOptions {
+    fn default() -> Self {
+        Self {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            allow_software: true,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::default(),
+            instance_flags: wgpu::InstanceFlags::from_build_config(),
+        }
+    }
+}
+
+impl GPUContext {
+    /// Creates a Vulkan context the Flutter engine can render with. Of the
+    /// adapters that meet the engine's needs and `options`, the one preferred
+    /// by `options.power_preference` is used, software adapters last. Fails
+    /// naming what the most preferred adapter lacks if none is suitable.
+    pub fn new_for_flutter(options: &GPUContextOptions) -> Result<Self, AppError> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::VULKAN,
+            flags: options.instance_flags,
+            backend_options: wgpu::BackendOptions::default(),
+        });
+        let mut adapters = instance.enumerate_adapters(wgpu::Backends::VULKAN);
+        adapters.sort_by_key(|adapter| {
+            flutter_device_vk::adapter_rank(&adapter.get_info(), options.power_preference)
+        });
+
+        let mut rejected = None;
+        for adapter in adapters {
+            let info = adapter.get_info();
+            let missing = flutter_device_vk::missing_requirements(&adapter, options);
+            if !missing.is_empty() {
+                debug!("Skipping {}, it lacks {}", info.name, missing.join(", "));
+                rejected.get_or_insert((info.name, missing));
+                continue;
+            }
+            let features = options.required_features
+                | (adapter.features() & flutter_device_vk::OPTIONAL_FEATURES);
+            let (device, queue) = flutter_device_vk::open_device(
+                &adapter,
+                features,
+                options.required_limits.clone(),
+            )?;
+            info!("Rendering with {} ({:?})", info.name, info.device_type);
+            let gpu_context = GPUContext {
+                instance,
+                adapter,
+                device,
+                queue,
+            };
+            gpu_context.validate_for_flutter()?;
+            return Ok(gpu_context);
+        }
+
+        let Some((adapter, missing)) = rejected else {
+            error!("No Vulkan adapter is available");
+            return Err(AppError::NoAdapter);
+        };
+        error!(
+            "{} cannot run Flutter, it lacks {}",
+            adapter,
+            missing.join(", ")
+        );
+        Err(AppError::MissingVulkanSupport { adapter, missing })
+    }
+
+    /// Checks that Flutter can render with this context: it must use Vulkan
+    /// 1.1 or later on a device that meets the engine's minimum, with the
+    /// device extensions the engine requires enabled. Contexts from
+    /// [`GPUContext::new_for_flutter`] always can.
+    pub fn validate_for_flutter(&self) -> Result<(), AppError> {
+        self.check_backend()?;
+        let mut missing = flutter_device_vk::unmet_device_minimum(&self.device)
+            .map(|(_, unmet)| unmet)
+            .unwrap_or_default();
+        missing
+            .extend(flutter_device_vk::missing_device_extensions(&self.device).unwrap_or_default());
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let adapter = self.adapter.get_info().name;
+        error!(
+            "{} cannot run Flutter, it lacks {}",
+            adapter,
+            missing.join(", ")
+        );
+        Err(AppError::MissingVulkanSupport { adapter, missing })
+    }
+
+    /// Flutter renders with Vulkan, so the context must use that backend.
+    pub(crate) fn check_backend(&self) -> Result<(), AppError> {
+        let backend = self.adapter.get_info().backend;
+        if backend != wgpu::Backend::Vulkan {
+            error!("The GPU context uses {}, Flutter needs Vulkan", backend);
+            return Err(AppError::UnsupportedBackend(backend));
+        }
+        Ok(())
+    }
+
+    /// Returns the flag set once the device is lost, e.g. by a driver reset.
+    /// This replaces the device lost callback, as wgpu keeps only one.
+    pub(crate) fn watch_device_loss(&self) -> Arc<AtomicBool> {
+        let lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = lost.clone();
+        self.device
+            .set_device_lost_callback(move |reason, message| {
+                error!("The GPU device was lost ({:?}): {}", reason, message);
+                lost_flag.store(true, Ordering::Relaxed);
+            });
+        lost
+    }
+
+    /// Creates a device like this context's on the same instance, preferring
+    /// the same adapter, to replace a lost one.
+    pub(crate) fn recreate(&self) -> Result<GPUContext, AppError> {
+        let info = self.adapter.get_info();
+        let mut adapters = self.instance.enumerate_adapters(wgpu::Backends::VULKAN);
+        let same_adapter = adapters.iter().position(|adapter| {
+            let candidate = adapter.get_info();
+            candidate.vendor == info.vendor && candidate.device == info.device
+        });
+        let adapter = match same_adapter {
+            Some(index) => adapters.swap_remove(index),
+            None if !adapters.is_empty() => {
+                warn!("{} is gone, moving to another adapter", info.name);
+                adapters.swap_remove(0)
+            }
+            None => {
+                error!("No Vulkan adapter is left to replace the lost device");
+                return Err(AppError::NoAdapter);
+            }
+        };
+        let (device, queue) =
+            flutter_device_vk::open_device(&adapter, self.device.features(), self.device.limits())?;
+        let gpu_context = GPUContext {
+            instance: self.instance.clone(),
+            adapter,
+            device,
+            queue,
+        };
+        gpu_context.validate_for_flutter()?;
+        Ok(gpu_context)
+    }
+}
+
 pub extern "C" fn get_instance_proc_address_callback(
     user_data: *mut ::core::ffi::c_void,
     _instance: FlutterVulkanInstanceHandle,
     name: *const ::core::ffi::c_char,
 ) -> *mut ::core::ffi::c_void {
-    let app = user_data as *mut AppWindowSession;
-    let name = unsafe { CStr::from_ptr(name) };
-    let expected = CStr::from_bytes_with_nul(b"vkGetInstanceProcAddr\0").unwrap();
-    if name != expected {
-        error!("unexpected");
-        return std::ptr::null_mut();
-    }
-    let app = unsafe { app.as_mut().unwrap() };
+    catch_callback_panic(
+        "get_instance_proc_address_callback",
+        std::ptr::null_mut(),
+        || {
+            let app = user_data as *mut AppWindowSession;
+            let name = unsafe { CStr::from_ptr(name) };
+            if name != c"vkGetInstanceProcAddr" {
+                error!("Unexpected instance proc address request for {:?}", name);
+                return std::ptr::null_mut();
+            }
+            let Some(app) = (unsafe { app.as_mut() }) else {
+                error!("Instance proc address requested without a session");
+                return std::ptr::null_mut();
+            };
 
-    app.compositor.get_instance_proc_address_callback()
+            app.compositors.get_instance_proc_address_callback()
+        },
+    )
 }
 
 #[derive(Clone, Debug)]
@@ -56,7 +251,53 @@ pub struct AppConfig {
     /// The path to the Flutter engine shared library.
     /// On Windows, this is typically a file named 'flutter_engine.dll'.
     /// The engine version should match the flutter
+    ///
+    /// Engines started from the same path share one loaded library and Dart VM.
     pub flutter_engine_path: std::path::PathBuf,
+    /// The Dart function to run instead of `main`. It must be annotated with
+    /// `@pragma('vm:entry-point')` so it is not tree shaken in AOT builds.
+    pub dart_entrypoint: Option<String>,
+    /// The arguments passed to the Dart entrypoint.
+    pub dart_entrypoint_args: Vec<String>,
+    /// Locales such as `en-US` or `zh-Hant-TW`, most preferred first, reported
+    /// instead of the system locales read from `LANGUAGE`, `LC_ALL`,
+    /// `LC_MESSAGES` and `LANG`.
+    pub locales: Option<Vec<String>>,
+    /// The source of brightness, text scale and accessibility preferences.
+    /// Defaults to [`OsSettings`], which follows the OS theme.
+    pub system_settings: Option<Arc<dyn SystemSettingsProvider>>,
+    /// Where to write `{"uri": ...}` once the VM service of a debug engine
+    /// listens, as the Dart VM's `--write-service-info` flag does, so tools
+    /// can attach without parsing the log.
+    pub vm_service_info_file: Option<PathBuf>,
+    /// Reloads the app when `flutter_assets` changes, e.g. after a build
+    /// wrote a new `kernel_blob.bin`. Meant for development with a debug engine.
+    pub watch_assets: bool,
+    /// Where the engine writes its timeline as a Perfetto trace, through its
+    /// `--trace-to-file` switch. The trace holds the engine's events and the
+    /// embedder spans mirrored by
+    /// [`EngineTimelineLayer`](crate::tracing_integration::EngineTimelineLayer)
+    /// on one clock.
+    ///
+    /// This is a separate file from the trace of
+    /// [`init_tracing`](crate::tracing_integration::init_tracing), which
+    /// records the spans with their fields on another clock. The two are not
+    /// merged; its `clock_sync` event lines them up.
+    ///
+    /// The switch configures the Dart VM, so only the engine that starts the
+    /// VM applies it.
+    pub engine_trace_file: Option<PathBuf>,
+    /// The tag the engine logs with, `flutter` by default. Engine logs are
+    /// routed to the `flutter::<tag>` target and Dart output to `dart::<tag>`.
+    pub log_tag: Option<String>,
+    /// Receives the output of the Dart program, e.g. from `print`, besides
+    /// it being logged.
+    pub on_dart_log: Option<DartLogCallback>,
+    /// Draws graphs of the recent frame times on top of every view, see
+    /// [`FrameStats`](crate::frame_stats::FrameStats).
+    pub performance_overlay: bool,
+    /// How window surfaces present, checked against what each surface supports.
+    pub surface_options: SurfaceOptions,
 }
 
 #[derive(Error, Debug)]
@@ -81,16 +322,121 @@ pub enum AppError {
 
     #[error("Failed to start event loop: {0}")]
     EventLoop(#[from] winit::error::EventLoopError),
+
+    #[error("Failed to create window: {0}")]
+    CreateWindow(#[from] winit::error::OsError),
+
+    #[error("No external texture registered with id {0}")]
+    UnknownTexture(i64),
+
+    #[error("No semantics node with id {0}")]
+    UnknownSemanticsNode(i32),
+
+    #[error("Semantics node {0} does not support {1:?}")]
+    UnsupportedSemanticsAction(i32, accesskit::Action),
+
+    #[error("Dart VM service error: {0}")]
+    VmService(String),
+
+    #[error("Failed to watch assets: {0}")]
+    AssetWatch(#[from] notify::Error),
+
+    #[error("The GPU context uses the {0} backend, but Flutter renders with Vulkan")]
+    UnsupportedBackend(wgpu::Backend),
+
+    #[error("Failed to create window surface: {0}")]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+
+    #[error("The window surface supports no format on this adapter")]
+    IncompatibleSurface,
+
+    #[error("No Vulkan adapter is available")]
+    NoAdapter,
+
+    #[error("Failed to create a GPU device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+
+    #[error("Failed to create the Vulkan device: {0}")]
+    CreateVulkanDevice(ash::vk::Result),
+
+    #[error("Failed to open the Vulkan device: {0}")]
+    OpenDevice(#[from] wgpu_hal::DeviceError),
+
+    #[error("{adapter} cannot run Flutter, it lacks {}", .missing.join(", "))]
+    MissingVulkanSupport {
+        adapter: String,
+        missing: Vec<String>,
+    },
+
+    #[error("The GPU device of a view drawn into a host texture was lost")]
+    DeviceLost,
+
+    #[error("Path is not valid UTF-8: {0}")]
+    NonUtf8Path(PathBuf),
+}
+
+/// Looks up an optional engine entry point, reporting it by its C name when missing.
+fn engine_proc<T>(proc: Option<T>, name: &str) -> Result<T, AppError> {
+    proc.ok_or_else(|| {
+        error!("{} not found", name);
+        AppError::FlutterEngineProcTable(name.to_string())
+    })
+}
+
+/// The length of a frame at `refresh_rate` frames per second.
+fn frame_interval_nanos(refresh_rate: f64) -> u64 {
+    (1e9 / refresh_rate) as u64
+}
+
+/// A window, or a texture owned by a host renderer, that shows one Flutter view.
+#[derive(Debug, Default)]
+struct SessionView {
+    window: Option<Arc<Window>>,
+    pointer: PointerState,
+    /// The display showing most of the view, sent with its metrics.
+    display_id: FlutterEngineDisplayId,
+    focused: bool,
+    occluded: bool,
 }
 
+/// The user data of the engine's add and remove view callbacks, which run on an
+/// engine thread and release or keep the view's compositor.
+struct ViewChange {
+    compositors: Arc<ViewCompositors>,
+    view_id: FlutterViewId,
+}
+
+/// A running engine and the views it renders to. The implicit view always
+/// exists; more windows can be added as views of the same engine.
+///
+/// Several sessions can run at once. Each passes itself as the user data of its
+/// engine callbacks, so callbacks never see the state of another engine.
 #[derive(Debug)]
-struct AppWindowSession {
+pub(crate) struct AppWindowSession {
     config: AppConfig,
-    _flutter_engine_lib: Library,
+    engine_library: Arc<EngineLibrary>,
     engine: flutter_embedder::FlutterEngineProcTable,
     engine_handle: FlutterEngine,
-    window: Arc<Window>,
-    compositor: Compositor,
+    compositors: Arc<ViewCompositors>,
+    views: HashMap<FlutterViewId, SessionView>,
+    last_view_id: FlutterViewId,
+    texture_registry: TextureRegistry,
+    platform_views: PlatformViewRegistry,
+    platform_task_runner: PlatformTaskRunner,
+    semantics: Semantics,
+    vm_service: VmService,
+    /// Exposes the semantics tree on the implicit view's window, if it has one.
+    accessibility_adapter: Option<AccessibilityAdapter>,
+    /// The displays last reported to the engine.
+    displays: Vec<Display>,
+    /// The refresh interval of the display showing the implicit view, in
+    /// nanoseconds, which vsync requests are answered with.
+    frame_interval_nanos: AtomicU64,
+    lifecycle_state: Option<AppLifecycleState>,
+    /// Set once Dart announced it answers `System.requestAppExit`.
+    dart_handles_exit: bool,
+    exit_requested: bool,
+    exit_approved: bool,
 }
 
 impl AppWindowSession {
@@ -98,83 +444,597 @@ impl AppWindowSession {
         config: AppConfig,
         window: Arc<Window>,
         gpu_context: GPUContext,
+        texture_registry: TextureRegistry,
+        platform_views: PlatformViewRegistry,
+        semantics: Semantics,
+        vm_service: VmService,
     ) -> Result<Self, AppError> {
-        let engine_lib = unsafe { Library::new(&config.flutter_engine_path)? };
-        let flutter_engine_get_proc_addresses = unsafe {
-            engine_lib.get::<fn(*mut FlutterEngineProcTable) -> FlutterEngineResult>(
-                b"FlutterEngineGetProcAddresses\0",
-            )?
-        };
-        if !config.flutter_engine_path.exists() {
-            error!(
-                "Engine not found at path: {}",
-                config.flutter_engine_path.display()
-            );
-            return Err(AppError::PathNoFound(config.flutter_engine_path.clone()));
-        }
-        let mut engine = flutter_embedder::FlutterEngineProcTable::default();
-        engine.struct_size = std::mem::size_of::<flutter_embedder::FlutterEngineProcTable>();
-        let res = flutter_engine_get_proc_addresses(&mut engine as *mut _ as _);
-        if res != FlutterEngineResult_kSuccess {
-            error!("Failed to get Flutter engine proc addresses: {:?}", res);
-            return Err(AppError::FlutterEngineError(res));
-        }
+        let compositor = Self::window_compositor(&window, gpu_context, &config.surface_options)?;
+        Self::with_compositor(
+            config,
+            Some(window),
+            compositor,
+            texture_registry,
+            platform_views,
+            semantics,
+            vm_service,
+        )
+    }
 
+    fn window_compositor(
+        window: &Arc<Window>,
+        gpu_context: GPUContext,
+        surface_options: &SurfaceOptions,
+    ) -> Result<Compositor, AppError> {
+        gpu_context.validate_for_flutter()?;
         let instance = gpu_context.instance;
         let device = gpu_context.device;
         let queue = gpu_context.queue;
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = instance.create_surface(window.clone()).map_err(|error| {
+            error!("Failed to create window surface: {}", error);
+            AppError::CreateSurface(error)
+        })?;
 
         let cap = surface.get_capabilities(&gpu_context.adapter);
-        let surface_format = cap.formats[0];
-
-        let initial_size = window.inner_size();
+        let Some(surface_config) = surface_options.configuration(&cap, window.inner_size()) else {
+            error!("The window surface supports no format on this adapter");
+            return Err(AppError::IncompatibleSurface);
+        };
 
         window.request_redraw();
 
+        Ok(crate::composition::Compositor::new(
+            instance,
+            device,
+            queue,
+            window.clone(),
+            surface,
+            surface_config,
+        ))
+    }
+
+    /// Creates the session with `compositor` drawing the implicit view.
+    pub(crate) fn with_compositor(
+        config: AppConfig,
+        window: Option<Arc<Window>>,
+        mut compositor: Compositor,
+        texture_registry: TextureRegistry,
+        platform_views: PlatformViewRegistry,
+        semantics: Semantics,
+        vm_service: VmService,
+    ) -> Result<Self, AppError> {
+        let engine_library = EngineLibrary::load(&config.flutter_engine_path)?;
+        let engine = engine_library.procs();
+        compositor.set_platform_views(platform_views.clone());
+        let implicit_view = SessionView {
+            window,
+            ..Default::default()
+        };
+        let compositors = ViewCompositors::new(compositor, config.performance_overlay);
+
         Ok(Self {
-            config: config,
-            window: window,
-            _flutter_engine_lib: engine_lib,
-            engine: engine,
+            config,
+            engine_library,
+            engine,
             engine_handle: std::ptr::null_mut(),
-            compositor: crate::composition::Compositor::new(
-                instance,
-                device,
-                queue,
-                surface,
-                surface_format,
-                initial_size,
-            ),
+            compositors: Arc::new(compositors),
+            views: HashMap::from([(IMPLICIT_VIEW_ID, implicit_view)]),
+            last_view_id: IMPLICIT_VIEW_ID,
+            texture_registry,
+            platform_views,
+            platform_task_runner: PlatformTaskRunner::new(),
+            semantics,
+            vm_service,
+            accessibility_adapter: None,
+            displays: Vec::new(),
+            frame_interval_nanos: AtomicU64::new(frame_interval_nanos(DEFAULT_REFRESH_RATE)),
+            lifecycle_state: None,
+            dart_handles_exit: false,
+            exit_requested: false,
+            exit_approved: false,
         })
     }
 
+    /// The stats of the frames presented to the views.
+    pub(crate) fn frame_stats(&self) -> FrameStatsStream {
+        self.compositors.frame_stats()
+    }
+
+    /// Delivers the frame stats to the subscribers of `frame_stats` instead.
+    pub(crate) fn set_frame_stats(&self, frame_stats: FrameStatsStream) {
+        self.compositors.set_frame_stats(frame_stats);
+    }
+
+    /// The textures kept for the engine's backing stores.
+    pub(crate) fn backing_store_pool(&self) -> BackingStorePool {
+        self.compositors.backing_store_pool()
+    }
+
+    /// Keeps the textures of released backing stores in `pool` instead.
+    pub(crate) fn set_backing_store_pool(&self, pool: BackingStorePool) {
+        self.compositors.set_backing_store_pool(pool);
+    }
+
+    /// Runs `f` with the compositor of `view_id`, if the view exists.
+    pub(crate) fn with_view_compositor<R>(
+        &self,
+        view_id: FlutterViewId,
+        f: impl FnOnce(&mut Compositor) -> R,
+    ) -> Option<R> {
+        self.compositors.with_view(view_id, f)
+    }
+
+    fn view_for_window(&self, window_id: WindowId) -> Option<FlutterViewId> {
+        self.views.iter().find_map(|(&view_id, view)| {
+            view.window
+                .as_ref()
+                .is_some_and(|window| window.id() == window_id)
+                .then_some(view_id)
+        })
+    }
+
+    /// Exposes the semantics tree to assistive technology through the implicit
+    /// view's window, then shows the window, which must still be invisible.
+    pub(crate) fn attach_accessibility(&mut self) {
+        let Some(window) = self.views[&IMPLICIT_VIEW_ID].window.clone() else {
+            return;
+        };
+        self.accessibility_adapter = Some(AccessibilityAdapter::new(&window, &self.semantics));
+        window.set_visible(true);
+    }
+
+    fn has_window(&self, window_id: WindowId) -> bool {
+        self.view_for_window(window_id).is_some()
+    }
+
+    /// Shows a new view of the engine in `window` and returns its view id.
+    pub(crate) fn add_window(
+        &mut self,
+        window: Arc<Window>,
+        gpu_context: GPUContext,
+    ) -> Result<FlutterViewId, AppError> {
+        let view_id = self.last_view_id + 1;
+
+        let mut compositor =
+            Self::window_compositor(&window, gpu_context, &self.config.surface_options)?;
+        compositor.set_platform_views(self.platform_views.clone());
+        self.compositors.insert(view_id, compositor);
+
+        let display_id = match self.add_engine_view(view_id, &window) {
+            Ok(display_id) => display_id,
+            Err(error) => {
+                self.compositors.remove(view_id);
+                return Err(error);
+            }
+        };
+
+        self.last_view_id = view_id;
+        self.views.insert(
+            view_id,
+            SessionView {
+                window: Some(window),
+                display_id,
+                ..Default::default()
+            },
+        );
+        Ok(view_id)
+    }
+
+    /// Tells the engine about a view whose compositor is already registered,
+    /// and returns the display it is on.
+    fn add_engine_view(
+        &self,
+        view_id: FlutterViewId,
+        window: &Window,
+    ) -> Result<FlutterEngineDisplayId, AppError> {
+        let add_view = self
+            .engine_library
+            .symbol::<FlutterEngineAddViewFnPtr>("FlutterEngineAddView")?;
+        let display_id = displays::current_display(window).unwrap_or_default();
+        let metrics = Self::window_metrics_event(
+            view_id,
+            window.inner_size(),
+            window.scale_factor(),
+            display_id,
+        );
+        let change = Box::into_raw(Box::new(ViewChange {
+            compositors: self.compositors.clone(),
+            view_id,
+        }));
+        let info = FlutterAddViewInfo {
+            struct_size: std::mem::size_of::<FlutterAddViewInfo>(),
+            view_id,
+            view_metrics: &metrics,
+            user_data: change as *mut ::core::ffi::c_void,
+            add_view_callback: Some(Self::add_view_callback),
+        };
+        let res = unsafe { add_view(self.engine_handle, &info) };
+        if res != FlutterEngineResult_kSuccess {
+            error!("FlutterEngineAddView failed for view {}: {}", view_id, res);
+            drop(unsafe { Box::from_raw(change) });
+            return Err(AppError::FlutterEngineError(res));
+        }
+        Ok(display_id)
+    }
+
+    /// Removes a view added with [`AppWindowSession::add_window`]. Its surface is
+    /// kept until the engine confirms the view is gone.
+    pub(crate) fn remove_view(&mut self, view_id: FlutterViewId) -> Result<(), AppError> {
+        let remove_view = self
+            .engine_library
+            .symbol::<FlutterEngineRemoveViewFnPtr>("FlutterEngineRemoveView")?;
+        let change = Box::into_raw(Box::new(ViewChange {
+            compositors: self.compositors.clone(),
+            view_id,
+        }));
+        let info = FlutterRemoveViewInfo {
+            struct_size: std::mem::size_of::<FlutterRemoveViewInfo>(),
+            view_id,
+            user_data: change as *mut ::core::ffi::c_void,
+            remove_view_callback: Some(Self::remove_view_callback),
+        };
+        let res = unsafe { remove_view(self.engine_handle, &info) };
+        if res != FlutterEngineResult_kSuccess {
+            error!(
+                "FlutterEngineRemoveView failed for view {}: {}",
+                view_id, res
+            );
+            drop(unsafe { Box::from_raw(change) });
+            return Err(AppError::FlutterEngineError(res));
+        }
+        self.views.remove(&view_id);
+        Ok(())
+    }
+
+    extern "C" fn add_view_callback(result: *const FlutterAddViewResult) {
+        catch_callback_panic("add_view_callback", (), || {
+            let result = unsafe { &*result };
+            let change = unsafe { Box::from_raw(result.user_data as *mut ViewChange) };
+            if result.added {
+                info!("Added view {}", change.view_id);
+            } else {
+                error!("Engine failed to add view {}", change.view_id);
+                change.compositors.remove(change.view_id);
+            }
+        })
+    }
+
+    extern "C" fn remove_view_callback(result: *const FlutterRemoveViewResult) {
+        catch_callback_panic("remove_view_callback", (), || {
+            let result = unsafe { &*result };
+            let change = unsafe { Box::from_raw(result.user_data as *mut ViewChange) };
+            if result.removed {
+                info!("Removed view {}", change.view_id);
+                change.compositors.remove(change.view_id);
+            } else {
+                error!("Engine failed to remove view {}", change.view_id);
+            }
+        })
+    }
+
+    /// Handles an event of one of the session's windows. Closing the implicit
+    /// view's window asks Dart whether to exit, see [`AppWindowSession::exit_approved`].
     #[instrument(level = "trace", skip_all)]
-    fn handle_window_event(&mut self, event: WindowEvent) -> bool {
+    fn handle_window_event(&mut self, window_id: WindowId, event: WindowEvent) {
+        let Some(view_id) = self.view_for_window(window_id) else {
+            return;
+        };
+        let window = self.views[&view_id].window.clone();
+        if view_id == IMPLICIT_VIEW_ID {
+            if let (Some(adapter), Some(window)) = (self.accessibility_adapter.as_mut(), &window) {
+                adapter.process_event(window, &event);
+            }
+        }
         match event {
             WindowEvent::CloseRequested => {
-                info!("Window closed");
-                return true;
+                info!("Window of view {} closed", view_id);
+                if view_id == IMPLICIT_VIEW_ID {
+                    self.request_exit();
+                } else if let Err(error) = self.remove_view(view_id) {
+                    error!("Failed to remove view {}: {}", view_id, error);
+                }
+            }
+            WindowEvent::Focused(focused) => {
+                if let Some(view) = self.views.get_mut(&view_id) {
+                    view.focused = focused;
+                }
+                self.update_lifecycle_state();
+            }
+            WindowEvent::Occluded(occluded) => {
+                if let Some(view) = self.views.get_mut(&view_id) {
+                    view.occluded = occluded;
+                }
+                self.update_lifecycle_state();
+            }
+            WindowEvent::Moved(_new_position) => match self.update_displays(view_id) {
+                Ok(true) => {
+                    if let Some(window) = &window {
+                        if let Err(error) = self.send_window_metrics_event(
+                            view_id,
+                            window.inner_size(),
+                            window.scale_factor(),
+                        ) {
+                            error!("Failed to send window metrics: {}", error);
+                        }
+                    }
+                }
+                Ok(false) => {}
+                Err(error) => error!("Failed to update displays: {}", error),
+            },
+            WindowEvent::ThemeChanged(theme) => {
+                if let Err(error) = self.send_system_settings(Some(theme)) {
+                    error!("Failed to send system settings: {}", error);
+                }
             }
-            WindowEvent::Moved(_new_position) => {}
             WindowEvent::Resized(new_size) => {
-                self.compositor.resize(new_size);
-                self.compositor.render();
-                // self.window.pre_present_notify();
-                self.compositor.present();
-                // self.window.request_redraw();
+                // Minimizing is only reported as a resize.
+                self.update_lifecycle_state();
+                self.compositors
+                    .with_view(view_id, |compositor| compositor.resize(new_size));
+                if let Some(window) = &window {
+                    if let Err(error) =
+                        self.send_window_metrics_event(view_id, new_size, window.scale_factor())
+                    {
+                        error!("Failed to send window metrics: {}", error);
+                    }
+                }
+                self.compositors.with_view(view_id, |compositor| {
+                    compositor.render();
+                    compositor.present();
+                });
             }
             WindowEvent::RedrawRequested => {
-                self.compositor.render();
-                self.window.pre_present_notify();
-                self.compositor.present();
-                self.window.request_redraw();
+                self.compositors.with_view(view_id, |compositor| {
+                    compositor.render();
+                    if let Some(window) = &window {
+                        window.pre_present_notify();
+                    }
+                    compositor.present();
+                });
+                if let Some(window) = &window {
+                    window.request_redraw();
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => self.pointer_moved(view_id, position),
+            WindowEvent::CursorLeft { .. } => self.pointer_left(view_id),
+            WindowEvent::MouseInput { state, button, .. } => {
+                if let Some(button) = PointerButton::from_winit(button) {
+                    self.pointer_button(view_id, button, state == ElementState::Pressed);
+                }
             }
             _ => {
                 info!("Window event: {:?}", event);
             }
         }
-        false
+    }
+
+    /// The lifecycle state of the app, from the most active of its windows.
+    fn lifecycle_state(&self) -> AppLifecycleState {
+        let windows = self
+            .views
+            .values()
+            .filter_map(|view| view.window.as_ref().map(|window| (view, window)))
+            .collect::<Vec<_>>();
+        let minimized = |window: &Window| window.is_minimized().unwrap_or(false);
+        if windows.is_empty() || windows.iter().any(|(view, _)| view.focused) {
+            AppLifecycleState::Resumed
+        } else if windows
+            .iter()
+            .any(|(view, window)| !view.occluded && !minimized(window))
+        {
+            AppLifecycleState::Inactive
+        } else if windows.iter().any(|(_, window)| !minimized(window)) {
+            AppLifecycleState::Hidden
+        } else {
+            AppLifecycleState::Paused
+        }
+    }
+
+    fn update_lifecycle_state(&mut self) {
+        self.set_lifecycle_state(self.lifecycle_state());
+    }
+
+    fn set_lifecycle_state(&mut self, state: AppLifecycleState) {
+        if self.lifecycle_state == Some(state) {
+            return;
+        }
+        debug!("Lifecycle state: {:?}", state);
+        match self.send_platform_message(LIFECYCLE_CHANNEL, state.message().as_bytes()) {
+            Ok(()) => self.lifecycle_state = Some(state),
+            Err(error) => error!("Failed to send lifecycle state: {}", error),
+        }
+    }
+
+    /// Asks Dart whether the app may exit, unless Dart never registered to be asked.
+    fn request_exit(&mut self) {
+        if !self.dart_handles_exit {
+            self.exit_approved = true;
+            return;
+        }
+        if self.exit_requested {
+            return;
+        }
+        let call = JsonMethodCall {
+            method: "System.requestAppExit".to_string(),
+            args: JsonValue::object([("type", "cancelable".into())]),
+        };
+        match self.send_platform_message_with_reply(
+            PLATFORM_CHANNEL,
+            &call.encode(),
+            Self::request_exit_reply_callback,
+        ) {
+            Ok(()) => self.exit_requested = true,
+            Err(error) => {
+                error!("Failed to ask Dart to exit: {}", error);
+                self.exit_approved = true;
+            }
+        }
+    }
+
+    extern "C" fn request_exit_reply_callback(
+        data: *const u8,
+        size: usize,
+        user_data: *mut ::core::ffi::c_void,
+    ) {
+        catch_callback_panic("request_exit_reply_callback", (), || {
+            let app = Self::user_data_to_self(&user_data);
+            app.exit_requested = false;
+            let reply = if data.is_null() {
+                &[][..]
+            } else {
+                unsafe { std::slice::from_raw_parts(data, size) }
+            };
+            app.exit_approved = match json_codec::decode_envelope(reply) {
+                Ok(Ok(reply)) => allows_exit(&reply),
+                Ok(Err(code)) => {
+                    warn!("System.requestAppExit failed with {}, exiting", code);
+                    true
+                }
+                Err(error) => {
+                    warn!("Invalid System.requestAppExit reply, exiting: {}", error);
+                    true
+                }
+            };
+            if !app.exit_approved {
+                info!("Dart cancelled the exit");
+            }
+        })
+    }
+
+    /// Shuts the engine down and starts a new one in the same windows, keeping
+    /// the GPU context and compositors. Added windows get their views back
+    /// under the same ids. Must not be called while platform tasks run.
+    pub(crate) fn restart_engine(&mut self) -> Result<(), AppError> {
+        info!("Restarting engine");
+        self.shutdown_engine();
+        self.reset_dart_state();
+        self.lifecycle_state = None;
+        self.displays.clear();
+        self.update_frame_interval();
+        for view in self.views.values_mut() {
+            view.pointer = PointerState::default();
+        }
+        self.initialize()?;
+
+        let added_views = self
+            .views
+            .iter()
+            .filter(|(&view_id, _)| view_id != IMPLICIT_VIEW_ID)
+            .filter_map(|(&view_id, view)| view.window.clone().map(|window| (view_id, window)))
+            .collect::<Vec<_>>();
+        for (view_id, window) in added_views {
+            match self.add_engine_view(view_id, &window) {
+                Ok(display_id) => {
+                    if let Some(view) = self.views.get_mut(&view_id) {
+                        view.display_id = display_id;
+                    }
+                }
+                Err(error) => {
+                    error!("Failed to add view {} again: {}", view_id, error);
+                    self.compositors.remove(view_id);
+                    self.views.remove(&view_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the engine to `gpu_context` after the device it rendered with was
+    /// lost. The engine holds on to the lost device, so it is restarted with
+    /// new surfaces and backing stores, and asked for a frame right away.
+    pub(crate) fn recover_device(&mut self, gpu_context: GPUContext) -> Result<(), AppError> {
+        info!("Moving the engine to a new GPU device");
+        self.shutdown_engine();
+        let frame_stats = self.compositors.frame_stats();
+        let backing_store_pool = self.compositors.backing_store_pool();
+        // The idle textures belong to the lost device.
+        backing_store_pool.trim();
+
+        let Some(implicit_window) = self
+            .views
+            .get(&IMPLICIT_VIEW_ID)
+            .and_then(|view| view.window.clone())
+        else {
+            error!("The host must replace the texture of the lost device");
+            return Err(AppError::DeviceLost);
+        };
+        let added_windows = self
+            .views
+            .iter()
+            .filter(|(&view_id, _)| view_id != IMPLICIT_VIEW_ID)
+            .filter_map(|(&view_id, view)| view.window.clone().map(|window| (view_id, window)))
+            .collect::<Vec<_>>();
+        // A window has one swapchain at a time, so the old surfaces go first.
+        let view_ids = self.views.keys().copied().collect::<Vec<_>>();
+        for view_id in view_ids {
+            self.compositors.remove(view_id);
+        }
+
+        let surface_options = &self.config.surface_options;
+        let mut implicit_view =
+            Self::window_compositor(&implicit_window, gpu_context.clone(), surface_options)?;
+        implicit_view.set_platform_views(self.platform_views.clone());
+        let compositors = ViewCompositors::new(implicit_view, self.config.performance_overlay);
+        for (view_id, window) in added_windows {
+            let mut compositor =
+                Self::window_compositor(&window, gpu_context.clone(), surface_options)?;
+            compositor.set_platform_views(self.platform_views.clone());
+            compositors.insert(view_id, compositor);
+        }
+        compositors.set_frame_stats(frame_stats);
+        compositors.set_backing_store_pool(backing_store_pool);
+        self.compositors = Arc::new(compositors);
+
+        self.restart_engine()?;
+        self.schedule_frame()
+    }
+
+    /// Asks the engine for a frame even if nothing changed in Dart.
+    fn schedule_frame(&self) -> Result<(), AppError> {
+        let schedule_frame = engine_proc(self.engine.ScheduleFrame, "FlutterEngineScheduleFrame")?;
+        let res = unsafe { schedule_frame(self.engine_handle) };
+        if res != FlutterEngineResult_kSuccess {
+            return Err(AppError::FlutterEngineError(res));
+        }
+        Ok(())
+    }
+
+    /// Shuts the engine down, if it runs. Windows and compositors are kept.
+    fn shutdown_engine(&mut self) {
+        if self.engine_handle.is_null() {
+            return;
+        }
+        self.set_lifecycle_state(AppLifecycleState::Detached);
+        self.texture_registry.unbind();
+        if let Some(shutdown) = self.engine.Shutdown {
+            unsafe { shutdown(self.engine_handle) };
+            self.engine_library.engine_stopped();
+        }
+        self.engine_handle = std::ptr::null_mut();
+        self.platform_task_runner.clear();
+    }
+
+    /// Forgets the state that belongs to the Dart isolate, which a hot restart
+    /// or an engine restart replaces.
+    fn reset_dart_state(&mut self) {
+        self.platform_views.dispose_all();
+        self.semantics.reset();
+        self.dart_handles_exit = false;
+        self.exit_requested = false;
+    }
+
+    /// Called on the platform thread before a hot restart reruns `main`.
+    extern "C" fn pre_engine_restart_callback(user_data: *mut ::core::ffi::c_void) {
+        catch_callback_panic("pre_engine_restart_callback", (), || {
+            info!("Hot restart");
+            Self::user_data_to_self(&user_data).reset_dart_state();
+        })
+    }
+
+    /// Whether the engine should shut down because its main window was closed
+    /// and Dart, if asked, allowed the app to exit.
+    pub(crate) fn exit_approved(&self) -> bool {
+        self.exit_approved
     }
 
     pub fn initialize(&mut self) -> Result<(), AppError> {
@@ -191,12 +1051,53 @@ impl AppWindowSession {
             return Err(AppError::PathNoFound(icu_data_path));
         }
 
-        let mut render_config = self.compositor.get_flutter_renderer_config();
-        let compositor_config = self.compositor.get_flutter_compositor();
+        let mut render_config = self.compositors.get_flutter_renderer_config()?;
+        let compositor_config = self.compositors.get_flutter_compositor();
 
         // let flutter_renderer_config = create_flutter_renderer_config(&instance, &device);
-        let asset_path_str = CString::new(assets_path.to_str().unwrap())?;
-        let icu_data_path_str = CString::new(icu_data_path.to_str().unwrap())?;
+        let path_str = |path: &PathBuf| -> Result<CString, AppError> {
+            let Some(path_str) = path.to_str() else {
+                error!("Path is not valid UTF-8: {:?}", path);
+                return Err(AppError::NonUtf8Path(path.clone()));
+            };
+            Ok(CString::new(path_str)?)
+        };
+        let asset_path_str = path_str(&assets_path)?;
+        let icu_data_path_str = path_str(&icu_data_path)?;
+        let entrypoint = self
+            .config
+            .dart_entrypoint
+            .as_deref()
+            .map(CString::new)
+            .transpose()?;
+        let entrypoint_args = self
+            .config
+            .dart_entrypoint_args
+            .iter()
+            .map(|arg| CString::new(arg.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut entrypoint_argv = entrypoint_args
+            .iter()
+            .map(|arg| arg.as_ptr())
+            .collect::<Vec<_>>();
+        let log_tag = self
+            .config
+            .log_tag
+            .as_deref()
+            .map(CString::new)
+            .transpose()?;
+        // The engine skips the first argument, as the program name.
+        let mut switches = vec![CString::new("flutter_rust_embedder")?];
+        if let Some(trace_file) = &self.config.engine_trace_file {
+            let trace_file = path_str(trace_file)?;
+            let mut switch = b"--trace-to-file=".to_vec();
+            switch.extend_from_slice(trace_file.as_bytes());
+            switches.push(CString::new(switch)?);
+        }
+        let mut switches_argv = switches
+            .iter()
+            .map(|switch| switch.as_ptr())
+            .collect::<Vec<_>>();
         let mut project_args = flutter_embedder::FlutterProjectArgs::default();
         project_args.struct_size = std::mem::size_of::<flutter_embedder::FlutterProjectArgs>();
         project_args.assets_path = asset_path_str.as_ptr();
@@ -211,11 +1112,34 @@ impl AppWindowSession {
         project_args.isolate_snapshot_instructions = std::ptr::null_mut();
         project_args.isolate_snapshot_instructions_size = 0;
         project_args.root_isolate_create_callback = Some(Self::root_isolate_create_callback);
+        project_args.custom_dart_entrypoint = entrypoint
+            .as_ref()
+            .map_or(std::ptr::null(), |entrypoint| entrypoint.as_ptr());
+        project_args.dart_entrypoint_argc = entrypoint_argv.len() as _;
+        project_args.dart_entrypoint_argv = entrypoint_argv.as_mut_ptr();
+        project_args.command_line_argc = switches_argv.len() as _;
+        project_args.command_line_argv = switches_argv.as_mut_ptr();
         project_args.update_semantics_callback = None;
-        project_args.custom_task_runners = std::ptr::null_mut();
+        project_args.update_semantics_callback2 = Some(Self::update_semantics_callback);
+        project_args.compute_platform_resolved_locale_callback = Some(resolve_locale_callback);
+        project_args.on_pre_engine_restart_callback = Some(Self::pre_engine_restart_callback);
+        // The engine copies the descriptions, but keeps the runner as user data.
+        let platform_task_runner = self.platform_task_runner.description();
+        let custom_task_runners = FlutterCustomTaskRunners {
+            struct_size: std::mem::size_of::<FlutterCustomTaskRunners>(),
+            platform_task_runner: &platform_task_runner,
+            ..Default::default()
+        };
+        project_args.custom_task_runners = &custom_task_runners;
+        // The VM is shared by every engine in the process. With this flag set on
+        // all of them the engine keeps it alive until the last one shuts down,
+        // while a single engine without it would leak the VM.
         project_args.shutdown_dart_vm_when_done = true;
         project_args.vsync_callback = Some(Self::vsync_callback);
         project_args.log_message_callback = Some(Self::log_message_callback);
+        project_args.log_tag = log_tag
+            .as_ref()
+            .map_or(std::ptr::null(), |log_tag| log_tag.as_ptr());
         project_args.compositor = &compositor_config as *const FlutterCompositor;
 
         let Some(initialize) = self.engine.Initialize else {
@@ -256,9 +1180,398 @@ impl AppWindowSession {
         }
 
         info!("FlutterEngineRunInitialized returned: {}", res);
+        self.engine_library.engine_started();
+
+        self.texture_registry.bind(self.engine_handle, &self.engine);
+        self.update_locales()?;
+        let theme = self.views[&IMPLICIT_VIEW_ID]
+            .window
+            .as_ref()
+            .and_then(|window| window.theme());
+        self.send_system_settings(theme)?;
+        self.update_displays(IMPLICIT_VIEW_ID)?;
+        self.update_lifecycle_state();
+
+        if let Some(window) = &self.views[&IMPLICIT_VIEW_ID].window {
+            self.send_window_metrics_event(
+                IMPLICIT_VIEW_ID,
+                window.inner_size(),
+                window.scale_factor(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Tells the engine the user's preferred locales.
+    fn update_locales(&self) -> Result<(), AppError> {
+        let locales = match &self.config.locales {
+            Some(tags) => parse_locales(tags),
+            None => system_locales(),
+        };
+        if locales.is_empty() {
+            debug!("No locales found, the engine keeps its default");
+            return Ok(());
+        }
+        info!("Locales: {:?}", locales);
+        let update_locales = engine_proc(self.engine.UpdateLocales, "FlutterEngineUpdateLocales")?;
+        set_preferred_locales(&locales);
+        let res = with_flutter_locales(&locales, |locales| unsafe {
+            update_locales(
+                self.engine_handle,
+                locales.as_ptr() as *mut _,
+                locales.len(),
+            )
+        });
+        if res != FlutterEngineResult_kSuccess {
+            error!("FlutterEngineUpdateLocales failed: {}", res);
+            return Err(AppError::FlutterEngineError(res));
+        }
+        Ok(())
+    }
+
+    /// Sends the user's preferences on `flutter/settings` and as accessibility features.
+    pub(crate) fn send_system_settings(&self, theme: Option<Theme>) -> Result<(), AppError> {
+        let settings = match &self.config.system_settings {
+            Some(provider) => provider.system_settings(theme),
+            None => OsSettings.system_settings(theme),
+        };
+        debug!("System settings: {:?}", settings);
+        self.send_platform_message(SETTINGS_CHANNEL, settings.to_json().as_bytes())?;
+
+        let update_accessibility_features = engine_proc(
+            self.engine.UpdateAccessibilityFeatures,
+            "FlutterEngineUpdateAccessibilityFeatures",
+        )?;
+        let res = unsafe {
+            update_accessibility_features(self.engine_handle, settings.accessibility_features())
+        };
+        if res != FlutterEngineResult_kSuccess {
+            error!("FlutterEngineUpdateAccessibilityFeatures failed: {}", res);
+            return Err(AppError::FlutterEngineError(res));
+        }
         Ok(())
     }
 
+    /// Sends a message to Dart that expects no reply.
+    fn send_platform_message(&self, channel: &str, data: &[u8]) -> Result<(), AppError> {
+        self.send_platform_message_with_handle(channel, data, std::ptr::null())
+    }
+
+    /// Sends a message to Dart whose reply is passed to `reply_callback`, with
+    /// the session as user data, on the platform thread.
+    fn send_platform_message_with_reply(
+        &mut self,
+        channel: &str,
+        data: &[u8],
+        reply_callback: extern "C" fn(*const u8, usize, *mut ::core::ffi::c_void),
+    ) -> Result<(), AppError> {
+        let create_response_handle = engine_proc(
+            self.engine.PlatformMessageCreateResponseHandle,
+            "FlutterPlatformMessageCreateResponseHandle",
+        )?;
+        let release_response_handle = engine_proc(
+            self.engine.PlatformMessageReleaseResponseHandle,
+            "FlutterPlatformMessageReleaseResponseHandle",
+        )?;
+        let mut response_handle = std::ptr::null_mut();
+        let res = unsafe {
+            create_response_handle(
+                self.engine_handle,
+                Some(reply_callback),
+                as_void_ptr(self),
+                &mut response_handle,
+            )
+        };
+        if res != FlutterEngineResult_kSuccess {
+            error!("FlutterPlatformMessageCreateResponseHandle failed: {}", res);
+            return Err(AppError::FlutterEngineError(res));
+        }
+        let result = self.send_platform_message_with_handle(channel, data, response_handle);
+        // The engine keeps what it needs to reply; the handle itself can go.
+        unsafe { release_response_handle(self.engine_handle, response_handle) };
+        result
+    }
+
+    fn send_platform_message_with_handle(
+        &self,
+        channel: &str,
+        data: &[u8],
+        response_handle: *const FlutterPlatformMessageResponseHandle,
+    ) -> Result<(), AppError> {
+        let send_platform_message = engine_proc(
+            self.engine.SendPlatformMessage,
+            "FlutterEngineSendPlatformMessage",
+        )?;
+        let channel = CString::new(channel)?;
+        let message = FlutterPlatformMessage {
+            struct_size: std::mem::size_of::<FlutterPlatformMessage>(),
+            channel: channel.as_ptr(),
+            message: data.as_ptr(),
+            message_size: data.len(),
+            response_handle,
+        };
+        let res = unsafe { send_platform_message(self.engine_handle, &message) };
+        if res != FlutterEngineResult_kSuccess {
+            error!(
+                "FlutterEngineSendPlatformMessage failed on {:?}: {}",
+                channel, res
+            );
+            return Err(AppError::FlutterEngineError(res));
+        }
+        Ok(())
+    }
+
+    /// Tells the engine about the displays if they changed since the last call,
+    /// and records the display showing `view_id`. Returns true when the view
+    /// moved to another display, so its metrics must be sent again.
+    fn update_displays(&mut self, view_id: FlutterViewId) -> Result<bool, AppError> {
+        let Some(window) = self
+            .views
+            .get(&view_id)
+            .and_then(|view| view.window.clone())
+        else {
+            return Ok(false);
+        };
+        let displays = displays::enumerate(&window);
+        if !displays.is_empty() && displays != self.displays {
+            info!("Displays: {:?}", displays);
+            let notify_display_update = engine_proc(
+                self.engine.NotifyDisplayUpdate,
+                "FlutterEngineNotifyDisplayUpdate",
+            )?;
+            let flutter_displays = displays::to_flutter(&displays);
+            let res = unsafe {
+                notify_display_update(
+                    self.engine_handle,
+                    FlutterEngineDisplaysUpdateType_kFlutterEngineDisplaysUpdateTypeStartup,
+                    flutter_displays.as_ptr(),
+                    flutter_displays.len(),
+                )
+            };
+            if res != FlutterEngineResult_kSuccess {
+                error!("FlutterEngineNotifyDisplayUpdate failed: {}", res);
+                return Err(AppError::FlutterEngineError(res));
+            }
+            self.displays = displays;
+        }
+
+        let display_id = displays::current_display(&window).unwrap_or_default();
+        let Some(view) = self.views.get_mut(&view_id) else {
+            return Ok(false);
+        };
+        let moved = view.display_id != display_id;
+        view.display_id = display_id;
+        self.update_frame_interval();
+        Ok(moved)
+    }
+
+    /// Stores the refresh interval of the display showing the implicit view
+    /// for the vsync callback.
+    fn update_frame_interval(&self) {
+        let display_id = self
+            .views
+            .get(&IMPLICIT_VIEW_ID)
+            .map_or(0, |view| view.display_id);
+        let refresh_rate = self
+            .displays
+            .iter()
+            .find(|display| display.id == display_id)
+            .map(|display| display.refresh_rate)
+            .filter(|&refresh_rate| refresh_rate > 0.0)
+            .unwrap_or(DEFAULT_REFRESH_RATE);
+        self.frame_interval_nanos
+            .store(frame_interval_nanos(refresh_rate), Ordering::Relaxed);
+    }
+
+    fn window_metrics_event(
+        view_id: FlutterViewId,
+        size: PhysicalSize<u32>,
+        pixel_ratio: f64,
+        display_id: FlutterEngineDisplayId,
+    ) -> FlutterWindowMetricsEvent {
+        FlutterWindowMetricsEvent {
+            struct_size: std::mem::size_of::<FlutterWindowMetricsEvent>(),
+            width: size.width as usize,
+            height: size.height as usize,
+            pixel_ratio,
+            display_id,
+            view_id,
+            ..Default::default()
+        }
+    }
+
+    /// Tells the engine the size of a view in physical pixels.
+    pub(crate) fn send_window_metrics_event(
+        &self,
+        view_id: FlutterViewId,
+        size: PhysicalSize<u32>,
+        pixel_ratio: f64,
+    ) -> Result<(), AppError> {
+        let send_window_metrics_event = engine_proc(
+            self.engine.SendWindowMetricsEvent,
+            "FlutterEngineSendWindowMetricsEvent",
+        )?;
+        let display_id = self.views.get(&view_id).map_or(0, |view| view.display_id);
+        let event = Self::window_metrics_event(view_id, size, pixel_ratio, display_id);
+        let res = unsafe { send_window_metrics_event(self.engine_handle, &event) };
+        if res != FlutterEngineResult_kSuccess {
+            return Err(AppError::FlutterEngineError(res));
+        }
+        Ok(())
+    }
+
+    /// Runs the platform tasks the engine posted that are due, including
+    /// platform message delivery. Must be called on the thread that created the session.
+    pub(crate) fn run_platform_tasks(&mut self) {
+        if self.engine_handle.is_null() {
+            return;
+        }
+        let Ok(run_task) = engine_proc(self.engine.RunTask, "FlutterEngineRunTask") else {
+            return;
+        };
+        let tasks = self
+            .platform_task_runner
+            .take_expired(self.current_time_nanos());
+        for task in tasks {
+            let res = unsafe { run_task(self.engine_handle, &task) };
+            if res != FlutterEngineResult_kSuccess {
+                error!("FlutterEngineRunTask failed: {}", res);
+            }
+        }
+        self.answer_vsyncs();
+        self.dispatch_accessibility_requests();
+    }
+
+    /// Answers the vsync requests the engine made since the last call.
+    fn answer_vsyncs(&self) {
+        let batons = self.platform_task_runner.take_vsync_batons();
+        if batons.is_empty() {
+            return;
+        }
+        let Ok(on_vsync) = engine_proc(self.engine.OnVsync, "FlutterEngineOnVsync") else {
+            return;
+        };
+        // There is no display timing source yet, so answer right away and
+        // assume the frame takes one refresh interval of the view's display.
+        let frame_interval = self.frame_interval_nanos.load(Ordering::Relaxed);
+        self.compositors
+            .vsync(std::time::Duration::from_nanos(frame_interval));
+        for baton in batons {
+            let frame_start = self.current_time_nanos();
+            let frame_target = frame_start + frame_interval;
+            let res = unsafe { on_vsync(self.engine_handle, baton, frame_start, frame_target) };
+            if res != FlutterEngineResult_kSuccess {
+                error!("FlutterEngineOnVsync failed: {}", res);
+            }
+        }
+    }
+
+    /// Passes what assistive technology asked for since the last call to the engine.
+    fn dispatch_accessibility_requests(&self) {
+        if let Some(enabled) = self.semantics.take_enabled_change() {
+            if let Ok(update_semantics_enabled) = engine_proc(
+                self.engine.UpdateSemanticsEnabled,
+                "FlutterEngineUpdateSemanticsEnabled",
+            ) {
+                let res = unsafe { update_semantics_enabled(self.engine_handle, enabled) };
+                if res != FlutterEngineResult_kSuccess {
+                    error!("FlutterEngineUpdateSemanticsEnabled failed: {}", res);
+                }
+            }
+        }
+        let requests = self.semantics.take_actions();
+        if requests.is_empty() {
+            return;
+        }
+        let Ok(dispatch_semantics_action) = engine_proc(
+            self.engine.DispatchSemanticsAction,
+            "FlutterEngineDispatchSemanticsAction",
+        ) else {
+            return;
+        };
+        for request in requests {
+            let res = unsafe {
+                dispatch_semantics_action(
+                    self.engine_handle,
+                    request.node_id,
+                    request.action,
+                    request.data.as_ptr(),
+                    request.data.len(),
+                )
+            };
+            if res != FlutterEngineResult_kSuccess {
+                error!("FlutterEngineDispatchSemanticsAction failed: {}", res);
+            }
+        }
+    }
+
+    pub(crate) fn pointer_moved(
+        &mut self,
+        view_id: FlutterViewId,
+        position: PhysicalPosition<f64>,
+    ) {
+        let Some(view) = self.views.get_mut(&view_id) else {
+            return;
+        };
+        let events = view.pointer.moved(position);
+        self.send_pointer_events(view_id, events);
+        self.platform_views.pointer_moved(view_id, position);
+    }
+
+    pub(crate) fn pointer_button(
+        &mut self,
+        view_id: FlutterViewId,
+        button: PointerButton,
+        pressed: bool,
+    ) {
+        let Some(view) = self.views.get_mut(&view_id) else {
+            return;
+        };
+        let events = view.pointer.button(button, pressed);
+        let position = view.pointer.position();
+        self.send_pointer_events(view_id, events);
+        self.platform_views
+            .pointer_button(view_id, button, pressed, position);
+    }
+
+    pub(crate) fn pointer_left(&mut self, view_id: FlutterViewId) {
+        let Some(view) = self.views.get_mut(&view_id) else {
+            return;
+        };
+        let events = view.pointer.left();
+        self.send_pointer_events(view_id, events);
+        self.platform_views.pointer_left();
+    }
+
+    fn send_pointer_events(&self, view_id: FlutterViewId, mut events: Vec<FlutterPointerEvent>) {
+        if events.is_empty() || self.engine_handle.is_null() {
+            return;
+        }
+        let Ok(send_pointer_event) = engine_proc(
+            self.engine.SendPointerEvent,
+            "FlutterEngineSendPointerEvent",
+        ) else {
+            return;
+        };
+        let timestamp = (self.current_time_nanos() / 1000) as usize;
+        for event in events.iter_mut() {
+            event.timestamp = timestamp;
+            event.view_id = view_id;
+        }
+        let res = unsafe { send_pointer_event(self.engine_handle, events.as_ptr(), events.len()) };
+        if res != FlutterEngineResult_kSuccess {
+            error!("FlutterEngineSendPointerEvent failed: {}", res);
+        }
+    }
+
+    /// The engine's monotonic clock, which input and vsync timestamps are expected to use.
+    fn current_time_nanos(&self) -> u64 {
+        self.engine
+            .GetCurrentTime
+            .map(|get_current_time| unsafe { get_current_time() })
+            .unwrap_or_default()
+    }
+
     fn user_data_to_self(user_data: &*mut std::ffi::c_void) -> &mut Self {
         let app = *user_data as *mut AppWindowSession;
         let app = unsafe { app.as_mut().unwrap() };
@@ -269,61 +1582,452 @@ impl AppWindowSession {
         message: *const FlutterPlatformMessage,
         user_data: *mut std::ffi::c_void,
     ) {
-        info!("platform_message_callback");
-        let app = Self::user_data_to_self(&user_data);
-        let message = unsafe { &*message };
-        app.handle_platform_message(message);
+        catch_callback_panic("platform_message_callback", (), || {
+            info!("platform_message_callback");
+            let app = Self::user_data_to_self(&user_data);
+            let message = unsafe { &*message };
+            app.handle_platform_message(message);
+        })
     }
 
     fn handle_platform_message(&mut self, message: &FlutterPlatformMessage) {
-        info!("Platform message received: {:?}", message);
+        let channel = unsafe { CStr::from_ptr(message.channel) }.to_string_lossy();
+        let data = if message.message.is_null() {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(message.message, message.message_size) }
+        };
+        // An empty response tells Dart the message was not handled.
+        let response = match channel.as_ref() {
+            PLATFORM_VIEWS_CHANNEL => match MethodCall::decode(data) {
+                Ok(call) => self.platform_views.handle_method_call(&call),
+                Err(error) => {
+                    warn!("Invalid message on {}: {}", channel, error);
+                    Vec::new()
+                }
+            },
+            PLATFORM_CHANNEL => match JsonMethodCall::decode(data) {
+                Ok(call) => self.handle_platform_method_call(&call),
+                Err(error) => {
+                    warn!("Invalid message on {}: {}", channel, error);
+                    Vec::new()
+                }
+            },
+            _ => {
+                debug!("Unhandled platform message on {}", channel);
+                Vec::new()
+            }
+        };
+        self.send_platform_message_response(message.response_handle, &response);
+    }
+
+    /// Handles the `flutter/platform` methods of app exit, which let Dart cancel
+    /// closing the main window.
+    fn handle_platform_method_call(&mut self, call: &JsonMethodCall) -> Vec<u8> {
+        match call.method.as_str() {
+            "System.initializationComplete" => {
+                self.dart_handles_exit = true;
+                json_codec::encode_success_envelope(&JsonValue::Null)
+            }
+            "System.exitApplication" => {
+                let exit_type = call.args.get("type").and_then(JsonValue::as_str);
+                let exit_code = call.args.get("exitCode").and_then(JsonValue::as_f64);
+                info!(
+                    "Dart requested exit {:?} with code {:?}",
+                    exit_type, exit_code
+                );
+                if exit_type == Some("required") {
+                    self.exit_approved = true;
+                    json_codec::encode_success_envelope(&exit_response(true))
+                } else {
+                    // Observers in Dart still get to cancel it.
+                    self.request_exit();
+                    json_codec::encode_success_envelope(&exit_response(false))
+                }
+            }
+            _ => {
+                debug!("Unhandled platform method {}", call.method);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Answers a platform message. Every message must be answered exactly once.
+    fn send_platform_message_response(
+        &self,
+        handle: *const FlutterPlatformMessageResponseHandle,
+        data: &[u8],
+    ) {
+        if handle.is_null() {
+            return;
+        }
+        let Ok(send_response) = engine_proc(
+            self.engine.SendPlatformMessageResponse,
+            "FlutterEngineSendPlatformMessageResponse",
+        ) else {
+            return;
+        };
+        let res = unsafe { send_response(self.engine_handle, handle, data.as_ptr(), data.len()) };
+        if res != FlutterEngineResult_kSuccess {
+            error!("FlutterEngineSendPlatformMessageResponse failed: {}", res);
+        }
+    }
+
+    extern "C" fn update_semantics_callback(
+        update: *const FlutterSemanticsUpdate2,
+        user_data: *mut std::ffi::c_void,
+    ) {
+        catch_callback_panic("update_semantics_callback", (), || {
+            let app = Self::user_data_to_self(&user_data);
+            let update = unsafe { &*update };
+            if let Some(tree_update) = app.semantics.apply_update(update) {
+                if let Some(adapter) = app.accessibility_adapter.as_mut() {
+                    adapter.update(tree_update);
+                }
+            }
+        })
     }
 
     extern "C" fn root_isolate_create_callback(_user_data: *mut std::ffi::c_void) {
-        info!("root_isolate_create_callback");
+        catch_callback_panic("root_isolate_create_callback", (), || {
+            info!("root_isolate_create_callback");
+        })
     }
 
     extern "C" fn vsync_callback(user_data: *mut ::core::ffi::c_void, baton: isize) {
-        let app = Self::user_data_to_self(&user_data);
-
-        info!("vsync_callback");
+        catch_callback_panic("vsync_callback", (), || {
+            // Called on an engine thread, so only the task runner, which the
+            // platform thread never replaces while the engine runs, is touched.
+            let app = unsafe { &*(user_data as *const Self) };
+            app.platform_task_runner.post_vsync(baton);
+        })
     }
 
     extern "C" fn log_message_callback(
         tag: *const ::core::ffi::c_char,
         message: *const ::core::ffi::c_char,
-        _arg3: *mut ::core::ffi::c_void,
+        user_data: *mut ::core::ffi::c_void,
     ) {
-        let tag = unsafe { CStr::from_ptr(tag) }.to_string_lossy();
-        let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
-        info!("Flutter Log {}:{}", tag, message);
+        catch_callback_panic("log_message_callback", (), || {
+            let tag = unsafe { CStr::from_ptr(tag) }.to_string_lossy();
+            let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+            // Logged from an engine thread, so only the config and the shared
+            // handle are touched.
+            let app = unsafe { &*(user_data as *const Self) };
+            engine_log::route(&tag, &message, app.config.on_dart_log.as_ref());
+            if let Some(uri) = vm_service::parse_service_uri(&message) {
+                app.vm_service.set_uri(uri);
+            }
+        })
     }
 }
 
 impl Drop for AppWindowSession {
     fn drop(&mut self) {
-        if let Some(shutdown) = self.engine.Shutdown {
-            if self.engine_handle != std::ptr::null_mut() {
-                unsafe { shutdown(self.engine_handle) };
-            }
+        self.shutdown_engine();
+        self.platform_views.dispose_all();
+        // The library itself is unloaded when the last session's `engine_library` drops.
+    }
+}
+/// Identifies an engine of an [`App`]. The engine created by [`App::new`] is
+/// [`EngineId::MAIN`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EngineId(usize);
+
+impl EngineId {
+    pub const MAIN: EngineId = EngineId(0);
+}
+
+/// Restarts an engine of a running [`App`] in its windows, from any thread,
+/// e.g. when a file watcher sees new Dart code. The restart happens the next
+/// time the app runs platform tasks.
+#[derive(Clone, Debug, Default)]
+pub struct EngineRestarter(Arc<AtomicBool>);
+
+impl EngineRestarter {
+    pub fn restart(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn take_request(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// An engine of the app with its own assets, entrypoint and main window.
+struct AppEngine {
+    config: AppConfig,
+    window_session: Option<Box<AppWindowSession>>,
+    texture_registry: TextureRegistry,
+    platform_views: PlatformViewRegistry,
+    semantics: Semantics,
+    vm_service: VmService,
+    frame_stats: FrameStatsStream,
+    backing_store_pool: BackingStorePool,
+    restarter: EngineRestarter,
+    asset_watcher: Option<AssetWatcher>,
+    pending_windows: Vec<WindowAttributes>,
+    /// Set once the engine's main window was closed, so it is not started again.
+    closed: bool,
+}
+
+impl AppEngine {
+    fn new(config: AppConfig) -> Self {
+        let vm_service = VmService::new(&config);
+        let texture_registry = TextureRegistry::new();
+        let platform_views = PlatformViewRegistry::new();
+        texture_registry.register_view_factory(&platform_views);
+        Self {
+            config,
+            window_session: None,
+            texture_registry,
+            platform_views,
+            semantics: Semantics::new(),
+            vm_service,
+            frame_stats: FrameStatsStream::default(),
+            backing_store_pool: BackingStorePool::default(),
+            restarter: EngineRestarter::default(),
+            asset_watcher: None,
+            pending_windows: Vec::new(),
+            closed: false,
+        }
+    }
+
+    fn start(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        gpu_context: &GPUContext,
+    ) -> Result<(), AppError> {
+        if self.window_session.is_some() || self.closed {
+            return Ok(());
+        }
+        // Shown once its accessibility adapter exists.
+        let attributes = WindowAttributes::default().with_visible(false);
+        let window = event_loop.create_window(attributes).map_err(|error| {
+            error!("Failed to create window {:?}", error);
+            AppError::CreateWindow(error)
+        })?;
+        info!("New window created");
+        let window_session = AppWindowSession::new(
+            self.config.clone(),
+            Arc::new(window),
+            gpu_context.clone(),
+            self.texture_registry.clone(),
+            self.platform_views.clone(),
+            self.semantics.clone(),
+            self.vm_service.clone(),
+        )?;
+
+        window_session.set_frame_stats(self.frame_stats.clone());
+        window_session.set_backing_store_pool(self.backing_store_pool.clone());
+
+        let mut pinned_session = Box::new(window_session);
+        pinned_session.initialize()?;
+        pinned_session.attach_accessibility();
+        self.asset_watcher = AssetWatcher::start(
+            &self.config,
+            self.vm_service.clone(),
+            self.restarter.clone(),
+        )?;
+
+        self.window_session = Some(pinned_session);
+        Ok(())
+    }
+
+    fn open_pending_windows(&mut self, event_loop: &ActiveEventLoop, gpu_context: &GPUContext) {
+        let Some(window_session) = self.window_session.as_mut() else {
+            return;
         };
+        for attributes in self.pending_windows.drain(..) {
+            let window = match event_loop.create_window(attributes) {
+                Ok(window) => window,
+                Err(error) => {
+                    error!("Failed to create window {:?}", error);
+                    continue;
+                }
+            };
+            match window_session.add_window(Arc::new(window), gpu_context.clone()) {
+                Ok(view_id) => info!("New window created for view {}", view_id),
+                Err(error) => error!("Failed to add window: {}", error),
+            }
+        }
     }
 }
+
+/// Runs one or more Flutter engines, each showing its main window and any
+/// windows added to it. Engines share the loaded engine library, the Dart VM
+/// and the GPU context, but nothing else.
+///
+/// Closing an engine's main window shuts that engine down; the app exits when
+/// every engine has been shut down.
 pub struct App {
-    config: AppConfig,
     gpu_context: GPUContext,
-    window_session: Option<Box<AppWindowSession>>,
+    /// Set when the device of `gpu_context` is lost, see [`App::recover_device`].
+    device_lost: Arc<AtomicBool>,
+    engines: Vec<AppEngine>,
+    /// The error that stopped the event loop, returned from [`App::run`].
+    error: Option<AppError>,
 }
 
 impl App {
+    /// Creates the app with the main engine. The app sets the device lost
+    /// callback of `gpu_context` to recover from device loss, replacing any
+    /// the host set. It keeps the uncaptured error handler of devices the host
+    /// created; wgpu's default one panics on the errors that follow a device
+    /// loss, so hosts that want to recover install one that does not.
+    /// Devices from [`GPUContext::new_for_flutter`] log these errors.
     pub fn new(config: AppConfig, gpu_context: GPUContext) -> Self {
+        let device_lost = gpu_context.watch_device_loss();
         Self {
-            config: config,
-            gpu_context: gpu_context,
-            window_session: None,
+            gpu_context,
+            device_lost,
+            engines: vec![AppEngine::new(config)],
+            error: None,
+        }
+    }
+
+    /// Adds an independent engine that starts with the app in its own window.
+    /// It can run other assets or another entrypoint than the main engine.
+    pub fn add_engine(&mut self, config: AppConfig) -> EngineId {
+        self.engines.push(AppEngine::new(config));
+        EngineId(self.engines.len() - 1)
+    }
+
+    /// The external textures of the main engine. Can be cloned to another thread
+    /// before calling [`App::run`]; ids registered before the engine starts are kept.
+    pub fn texture_registry(&self) -> TextureRegistry {
+        self.engine_texture_registry(EngineId::MAIN)
+    }
+
+    /// The platform view factories of the main engine. Register them before calling [`App::run`].
+    pub fn platform_views(&self) -> PlatformViewRegistry {
+        self.engine_platform_views(EngineId::MAIN)
+    }
+
+    /// The semantics tree of the main engine, which tests can query and act on.
+    pub fn semantics(&self) -> Semantics {
+        self.engine_semantics(EngineId::MAIN)
+    }
+
+    /// The Dart VM service of the main engine, for hot reload and hot restart
+    /// when it is a debug engine.
+    pub fn vm_service(&self) -> VmService {
+        self.engine_vm_service(EngineId::MAIN)
+    }
+
+    /// The timing of the frames the main engine presents to its windows.
+    pub fn frame_stats(&self) -> FrameStatsStream {
+        self.engine_frame_stats(EngineId::MAIN)
+    }
+
+    /// The textures the main engine reuses for its backing stores.
+    pub fn backing_store_pool(&self) -> BackingStorePool {
+        self.engine_backing_store_pool(EngineId::MAIN)
+    }
+
+    /// Restarts the main engine in its windows, keeping the GPU context.
+    pub fn restarter(&self) -> EngineRestarter {
+        self.engine_restarter(EngineId::MAIN)
+    }
+
+    /// The external textures of `engine`, see [`App::texture_registry`].
+    pub fn engine_texture_registry(&self, engine: EngineId) -> TextureRegistry {
+        self.engines[engine.0].texture_registry.clone()
+    }
+
+    /// The platform view factories of `engine`, see [`App::platform_views`].
+    pub fn engine_platform_views(&self, engine: EngineId) -> PlatformViewRegistry {
+        self.engines[engine.0].platform_views.clone()
+    }
+
+    /// The semantics tree of `engine`, see [`App::semantics`].
+    pub fn engine_semantics(&self, engine: EngineId) -> Semantics {
+        self.engines[engine.0].semantics.clone()
+    }
+
+    /// The Dart VM service of `engine`, see [`App::vm_service`].
+    pub fn engine_vm_service(&self, engine: EngineId) -> VmService {
+        self.engines[engine.0].vm_service.clone()
+    }
+
+    /// The frame timing of `engine`, see [`App::frame_stats`].
+    pub fn engine_frame_stats(&self, engine: EngineId) -> FrameStatsStream {
+        self.engines[engine.0].frame_stats.clone()
+    }
+
+    /// The backing store textures of `engine`, see [`App::backing_store_pool`].
+    pub fn engine_backing_store_pool(&self, engine: EngineId) -> BackingStorePool {
+        self.engines[engine.0].backing_store_pool.clone()
+    }
+
+    /// Restarts `engine`, see [`App::restarter`].
+    pub fn engine_restarter(&self, engine: EngineId) -> EngineRestarter {
+        self.engines[engine.0].restarter.clone()
+    }
+
+    /// Opens another window that shows a new view of the main engine, so it
+    /// shares Dart state with the main window. Windows requested before
+    /// [`App::run`] open once the engine is running. The Dart app must render
+    /// into every view of `PlatformDispatcher.views`, e.g. with `runWidget`.
+    ///
+    /// Closing the window removes its view; closing the main window shuts the engine down.
+    pub fn add_window(&mut self, attributes: WindowAttributes) {
+        self.add_engine_window(EngineId::MAIN, attributes);
+    }
+
+    /// Opens another window that shows a new view of `engine`, see [`App::add_window`].
+    pub fn add_engine_window(&mut self, engine: EngineId, attributes: WindowAttributes) {
+        self.engines[engine.0].pending_windows.push(attributes);
+    }
+
+    fn open_pending_windows(&mut self, event_loop: &ActiveEventLoop) {
+        for engine in self.engines.iter_mut() {
+            engine.open_pending_windows(event_loop, &self.gpu_context);
         }
     }
 
+    /// Replaces the lost device, e.g. after a driver reset, and restarts every
+    /// engine on the new one. Dart state does not survive this.
+    fn recover_device(&mut self) -> Result<(), AppError> {
+        warn!("Recovering from the loss of the GPU device");
+        let gpu_context = self.gpu_context.recreate()?;
+        self.device_lost = gpu_context.watch_device_loss();
+        self.gpu_context = gpu_context;
+        for engine in self.engines.iter_mut() {
+            if let Some(window_session) = engine.window_session.as_mut() {
+                window_session.recover_device(self.gpu_context.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Shuts down the engines whose exit was approved, and exits once none is left.
+    fn close_exited_engines(&mut self, event_loop: &ActiveEventLoop) {
+        let mut closed_any = false;
+        for engine in self.engines.iter_mut() {
+            if engine
+                .window_session
+                .as_ref()
+                .is_some_and(|session| session.exit_approved())
+            {
+                engine.window_session = None;
+                engine.asset_watcher = None;
+                engine.closed = true;
+                closed_any = true;
+            }
+        }
+        if closed_any
+            && self
+                .engines
+                .iter()
+                .all(|engine| engine.window_session.is_none())
+        {
+            event_loop.exit();
+        }
+    }
+
+    /// Runs the event loop until every engine has shut down. Fails with the
+    /// error that kept an engine from starting.
     pub fn run(&mut self) -> Result<(), AppError> {
         let mut event_loop = EventLoop::new()?;
         // event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
@@ -340,35 +2044,44 @@ impl App {
                 info!("exit code {}", val);
                 break;
             }
+
+            if self.device_lost.load(Ordering::Relaxed) {
+                if let Err(error) = self.recover_device() {
+                    error!("Failed to recover from device loss: {}", error);
+                    self.error = Some(error);
+                    break;
+                }
+            }
+
+            for engine in self.engines.iter_mut() {
+                if let Some(window_session) = engine.window_session.as_mut() {
+                    if engine.restarter.take_request() {
+                        if let Err(error) = window_session.restart_engine() {
+                            error!("Failed to restart engine: {}", error);
+                        }
+                    }
+                    window_session.run_platform_tasks();
+                }
+            }
+        }
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
         }
-        Ok(())
     }
 }
 
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window_attributes = WindowAttributes::default();
-        let window = event_loop.create_window(window_attributes);
-
-        match window {
-            Ok(window) => {
-                info!("New window created");
-                let window_session = AppWindowSession::new(
-                    self.config.clone(),
-                    Arc::new(window),
-                    self.gpu_context.clone(),
-                )
-                .unwrap();
-
-                let mut pinned_session = Box::new(window_session);
-                pinned_session.initialize().unwrap();
-
-                self.window_session = Some(pinned_session);
-            }
-            Err(error) => {
-                error!("Failed to create window {:?}", error);
+        for engine in self.engines.iter_mut() {
+            if let Err(error) = engine.start(event_loop, &self.gpu_context) {
+                error!("Failed to start engine: {}", error);
+                self.error = Some(error);
+                event_loop.exit();
+                return;
             }
         }
+        self.open_pending_windows(event_loop);
     }
 
     fn window_event(
@@ -377,18 +2090,31 @@ impl ApplicationHandler for App {
         window_id: WindowId,
         event: WindowEvent,
     ) {
-        let Some(window_session) = self
-            .window_session
-            .as_mut()
-            .filter(|window_session| window_session.window.id() == window_id)
-        else {
+        let Some(engine) = self.engines.iter_mut().find(|engine| {
+            engine
+                .window_session
+                .as_ref()
+                .is_some_and(|session| session.has_window(window_id))
+        }) else {
+            return;
+        };
+        let Some(window_session) = engine.window_session.as_mut() else {
             return;
         };
 
-        let should_close = window_session.handle_window_event(event);
+        window_session.handle_window_event(window_id, event);
+        self.close_exited_engines(event_loop);
+    }
 
-        if should_close {
-            event_loop.exit();
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Dart's answers to exit requests arrive while platform tasks run.
+        self.close_exited_engines(event_loop);
+        self.open_pending_windows(event_loop);
+    }
+
+    fn memory_warning(&mut self, _event_loop: &ActiveEventLoop) {
+        for engine in &self.engines {
+            engine.backing_store_pool.trim();
         }
     }
 }
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{debug, error, info, warn};
+
+use crate::application::{AppConfig, AppError, EngineRestarter};
+use crate::vm_service::VmService;
+
+/// How long the assets must stay unchanged before reloading, since a build
+/// writes many files.
+const QUIET_PERIOD: Duration = Duration::from_millis(300);
+
+const KERNEL_BLOB: &str = "kernel_blob.bin";
+
+/// Watches `flutter_assets` of an engine started with [`AppConfig::watch_assets`]
+/// and hot reloads it when the kernel blob or an asset changes. Without a VM
+/// service, or when the reload fails, the engine is restarted instead.
+///
+/// Watching stops when this is dropped.
+pub(crate) struct AssetWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl AssetWatcher {
+    /// Starts watching if the config asks for it.
+    pub(crate) fn start(
+        config: &AppConfig,
+        vm_service: VmService,
+        restarter: EngineRestarter,
+    ) -> Result<Option<Self>, AppError> {
+        if !config.watch_assets {
+            return Ok(None);
+        }
+        let flutter_assets = config.asset_dir.join("flutter_assets");
+        // Notifications carry absolute paths.
+        let flutter_assets = flutter_assets.canonicalize().unwrap_or(flutter_assets);
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if event.kind.is_create() || event.kind.is_modify() => {
+                    for path in event.paths {
+                        // Fails only once the reload thread is gone.
+                        let _ = sender.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => warn!("Asset watcher error: {}", error),
+            })?;
+        watcher.watch(&flutter_assets, RecursiveMode::Recursive)?;
+        info!("Watching {:?} for changes", flutter_assets);
+
+        std::thread::Builder::new()
+            .name("asset watcher".to_string())
+            .spawn(move || {
+                // Ends when the watcher, which owns the sender, is dropped.
+                while let Ok(path) = receiver.recv() {
+                    let mut changed = HashSet::from([path]);
+                    while let Ok(path) = receiver.recv_timeout(QUIET_PERIOD) {
+                        changed.insert(path);
+                    }
+                    reload(&flutter_assets, &changed, &vm_service, &restarter);
+                }
+            })
+            .map_err(|error| {
+                error!("Failed to start the asset watcher thread: {}", error);
+                AppError::AssetWatch(notify::Error::io(error))
+            })?;
+        Ok(Some(Self { _watcher: watcher }))
+    }
+}
+
+fn reload(
+    flutter_assets: &Path,
+    changed: &HashSet<PathBuf>,
+    vm_service: &VmService,
+    restarter: &EngineRestarter,
+) {
+    debug!("Assets changed: {:?}", changed);
+    if vm_service.uri().is_none() {
+        info!("Assets changed without a Dart VM service, restarting the engine");
+        restarter.restart();
+        return;
+    }
+    // Asset keys are paths relative to `flutter_assets`, with forward slashes.
+    let asset_keys = changed
+        .iter()
+        .filter(|path| path.file_name().is_some_and(|name| name != KERNEL_BLOB))
+        .filter_map(|path| path.strip_prefix(flutter_assets).ok())
+        .map(|key| key.to_string_lossy().replace('\\', "/"))
+        .collect::<Vec<_>>();
+    let result = vm_service
+        .evict_assets(&asset_keys)
+        .and_then(|()| vm_service.hot_reload());
+    if let Err(error) = result {
+        error!("Hot reload failed, restarting the engine: {}", error);
+        restarter.restart();
+    }
+}
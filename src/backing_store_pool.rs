@@ -0,0 +1,273 @@
+//! Reuse of the textures lent to the engine as backing stores, so resizing a
+//! window or adding and removing platform views does not allocate a texture
+//! for every frame.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use tracing::debug;
+
+/// Bytes of idle textures a pool keeps by default.
+pub const DEFAULT_BUDGET: u64 = 64 * 1024 * 1024;
+
+/// What a texture must match to be reused for a backing store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TextureKey {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) format: wgpu::TextureFormat,
+    pub(crate) usage: wgpu::TextureUsages,
+}
+
+impl TextureKey {
+    fn of(texture: &wgpu::Texture) -> Self {
+        Self {
+            width: texture.width(),
+            height: texture.height(),
+            format: texture.format(),
+            usage: texture.usage(),
+        }
+    }
+
+    fn bytes(&self) -> u64 {
+        let texel = self.format.block_copy_size(None).unwrap_or(4);
+        self.width as u64 * self.height as u64 * texel as u64
+    }
+}
+
+/// Counters of a [`BackingStorePool`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BackingStorePoolStats {
+    /// Textures created because no idle one matched.
+    pub allocated: u64,
+    /// Backing stores served from an idle texture.
+    pub reused: u64,
+    /// Idle textures released to stay within the budget or by a trim.
+    pub evicted: u64,
+    /// Backing stores the engine holds.
+    pub in_use: usize,
+    /// Textures kept for reuse.
+    pub idle: usize,
+    pub idle_bytes: u64,
+}
+
+/// The idle textures and counters of a pool, generic over the texture so the
+/// bookkeeping works without a device.
+#[derive(Debug)]
+struct PoolState<T> {
+    budget: u64,
+    /// Least recently released first.
+    idle: VecDeque<(TextureKey, T)>,
+    stats: BackingStorePoolStats,
+}
+
+/// The textures an engine released as backing stores, kept for the next
+/// backing store of the same size, format and usage. The least recently
+/// released ones are dropped once the idle textures exceed the budget.
+#[derive(Clone, Debug)]
+pub struct BackingStorePool(Arc<Mutex<PoolState<wgpu::Texture>>>);
+
+impl Default for BackingStorePool {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(PoolState::default())))
+    }
+}
+
+impl BackingStorePool {
+    pub fn stats(&self) -> BackingStorePoolStats {
+        self.0.lock().unwrap().stats
+    }
+
+    /// Sets the bytes of idle textures to keep, evicting the oldest ones over
+    /// it. A budget of 0 turns reuse off.
+    pub fn set_budget(&self, budget: u64) {
+        self.0.lock().unwrap().set_budget(budget);
+    }
+
+    /// Releases every idle texture, e.g. when the OS is low on memory.
+    pub fn trim(&self) {
+        self.0.lock().unwrap().trim();
+    }
+
+    /// Takes the most recently released idle texture matching `key`.
+    pub(crate) fn take(&self, key: &TextureKey) -> Option<wgpu::Texture> {
+        self.0.lock().unwrap().take(key)
+    }
+
+    /// Counts a texture created for a backing store because `take` had none.
+    pub(crate) fn allocated(&self) {
+        self.0.lock().unwrap().allocated();
+    }
+
+    /// Keeps the texture of a backing store the engine released.
+    pub(crate) fn recycle(&self, texture: wgpu::Texture) {
+        let key = TextureKey::of(&texture);
+        self.0.lock().unwrap().recycle(key, texture);
+    }
+}
+
+impl<T> Default for PoolState<T> {
+    fn default() -> Self {
+        Self {
+            budget: DEFAULT_BUDGET,
+            idle: VecDeque::new(),
+            stats: BackingStorePoolStats::default(),
+        }
+    }
+}
+
+impl<T> PoolState<T> {
+    fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+        self.evict_over_budget();
+    }
+
+    fn trim(&mut self) {
+        let evicted = self.idle.len();
+        self.idle.clear();
+        self.stats.evicted += evicted as u64;
+        self.stats.idle = 0;
+        self.stats.idle_bytes = 0;
+        if evicted > 0 {
+            debug!("Trimmed {} idle backing stores", evicted);
+        }
+    }
+
+    fn take(&mut self, key: &TextureKey) -> Option<T> {
+        let index = self.idle.iter().rposition(|(idle, _)| idle == key)?;
+        let (_, texture) = self.idle.remove(index)?;
+        self.stats.reused += 1;
+        self.stats.in_use += 1;
+        self.stats.idle -= 1;
+        self.stats.idle_bytes -= key.bytes();
+        Some(texture)
+    }
+
+    fn allocated(&mut self) {
+        self.stats.allocated += 1;
+        self.stats.in_use += 1;
+    }
+
+    fn recycle(&mut self, key: TextureKey, texture: T) {
+        // Every recycled texture was taken or allocated, but an unbalanced
+        // release should skew the stats rather than wrap them.
+        self.stats.in_use = self.stats.in_use.saturating_sub(1);
+        self.idle.push_back((key, texture));
+        self.stats.idle += 1;
+        self.stats.idle_bytes += key.bytes();
+        self.evict_over_budget();
+    }
+
+    fn evict_over_budget(&mut self) {
+        while self.stats.idle_bytes > self.budget {
+            let Some((key, _)) = self.idle.pop_front() else {
+                break;
+            };
+            self.stats.evicted += 1;
+            self.stats.idle -= 1;
+            self.stats.idle_bytes -= key.bytes();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(width: u32, height: u32) -> TextureKey {
+        TextureKey {
+            width,
+            height,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        }
+    }
+
+    /// A pool of texture ids, each used once.
+    fn pool(budget: u64) -> PoolState<u32> {
+        PoolState {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reuses_the_most_recently_released_match() {
+        let mut pool = pool(DEFAULT_BUDGET);
+        for _ in 0..3 {
+            pool.allocated();
+        }
+        pool.recycle(key(10, 10), 1);
+        pool.recycle(key(20, 20), 2);
+        pool.recycle(key(10, 10), 3);
+        assert_eq!(pool.stats.in_use, 0);
+        assert_eq!(pool.stats.idle, 3);
+        assert_eq!(pool.stats.idle_bytes, 4 * (100 + 400 + 100));
+
+        assert_eq!(pool.take(&key(10, 10)), Some(3));
+        assert_eq!(pool.take(&key(10, 10)), Some(1));
+        assert_eq!(pool.take(&key(10, 10)), None);
+        assert_eq!(pool.take(&key(10, 20)), None);
+        let stats = pool.stats;
+        assert_eq!((stats.allocated, stats.reused, stats.evicted), (3, 2, 0));
+        assert_eq!(
+            (stats.in_use, stats.idle, stats.idle_bytes),
+            (2, 1, 4 * 400)
+        );
+    }
+
+    #[test]
+    fn evicts_the_least_recently_released_over_budget() {
+        let mut pool = pool(4 * 350);
+        pool.recycle(key(10, 10), 1);
+        pool.recycle(key(10, 10), 2);
+        pool.recycle(key(20, 10), 3);
+        pool.recycle(key(10, 5), 4);
+        assert_eq!(
+            pool.idle.iter().map(|(_, id)| *id).collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        assert_eq!(pool.stats.evicted, 1);
+        assert_eq!(pool.stats.idle, 3);
+        assert_eq!(pool.stats.idle_bytes, 4 * 350);
+
+        pool.set_budget(4 * 300);
+        assert_eq!(
+            pool.idle.iter().map(|(_, id)| *id).collect::<Vec<_>>(),
+            [3, 4]
+        );
+        assert_eq!(pool.stats.evicted, 2);
+        assert_eq!(pool.stats.idle_bytes, 4 * 250);
+    }
+
+    #[test]
+    fn a_budget_of_zero_keeps_nothing() {
+        let mut pool = pool(DEFAULT_BUDGET);
+        pool.recycle(key(10, 10), 1);
+        pool.recycle(key(10, 10), 2);
+        pool.set_budget(0);
+        assert!(pool.idle.is_empty());
+        assert_eq!((pool.stats.idle, pool.stats.idle_bytes), (0, 0));
+        assert_eq!(pool.stats.evicted, 2);
+
+        pool.recycle(key(10, 10), 3);
+        assert_eq!(pool.take(&key(10, 10)), None);
+        assert_eq!(pool.stats.evicted, 3);
+    }
+
+    #[test]
+    fn trim_releases_every_idle_texture() {
+        let mut pool = pool(DEFAULT_BUDGET);
+        pool.allocated();
+        pool.allocated();
+        pool.recycle(key(10, 10), 1);
+        pool.recycle(key(20, 20), 2);
+        pool.recycle(key(30, 30), 3);
+        pool.trim();
+        let stats = pool.stats;
+        assert_eq!((stats.idle, stats.idle_bytes, stats.evicted), (0, 0, 3));
+        // The third texture was recycled without being counted as in use.
+        assert_eq!(stats.in_use, 0);
+        assert_eq!(pool.take(&key(10, 10)), None);
+    }
+}
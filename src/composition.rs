@@ -1,24 +1,510 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use ash::vk::Handle;
-use tracing::instrument;
+use tracing::{debug, error, instrument, warn};
+use wgpu::util::DeviceExt;
 
 use crate::{
+    application::AppError,
+    backing_store_pool::{BackingStorePool, TextureKey},
     flutter_embedder::{
-        FlutterBackingStore, FlutterBackingStoreConfig, FlutterCompositor, FlutterLayer,
-        FlutterRendererConfig, FlutterVulkanInstanceHandle, FlutterVulkanRendererConfig,
+        FlutterBackingStore, FlutterBackingStoreConfig,
+        FlutterBackingStoreType_kFlutterBackingStoreTypeVulkan, FlutterCompositor, FlutterLayer,
+        FlutterLayerContentType_kFlutterLayerContentTypeBackingStore,
+        FlutterLayerContentType_kFlutterLayerContentTypePlatformView, FlutterPlatformView,
+        FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRect,
+        FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRoundedRect,
+        FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeOpacity,
+        FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeTransformation,
+        FlutterPresentViewInfo, FlutterRect, FlutterTransformation, FlutterViewId,
+        FlutterVulkanBackingStore, FlutterVulkanImage,
     },
     flutter_render_config_vk::{create_flutter_renderer_config, FlutterRendererConfigWrapper},
-    utils::as_void_ptr,
+    frame_stats::{FrameStats, FrameStatsStream, FrameTimer, GpuTimer, PerformanceOverlay},
+    platform_views::{PlatformViewPlacement, PlatformViewRegistry},
+    surface,
+    utils::catch_callback_panic,
+};
+
+/// The view the engine creates on its own. It cannot be removed.
+pub(crate) const IMPLICIT_VIEW_ID: FlutterViewId = 0;
+
+/// Format of the images handed to the engine as backing stores.
+const BACKING_STORE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const BACKING_STORE_VK_FORMAT: ash::vk::Format = ash::vk::Format::R8G8B8A8_UNORM;
+const BACKING_STORE_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::RENDER_ATTACHMENT
+    .union(wgpu::TextureUsages::TEXTURE_BINDING)
+    .union(wgpu::TextureUsages::COPY_SRC)
+    .union(wgpu::TextureUsages::COPY_DST);
+
+/// Size of `LayerUniforms` in `composite.wgsl`, including trailing padding.
+const LAYER_UNIFORMS_SIZE: usize = 80;
+
+/// Where the compositor draws the Flutter layers.
+#[derive(Debug)]
+pub enum CompositorTarget {
+    /// A window surface, presented after every frame.
+    Surface(wgpu::Surface<'static>),
+    /// A caller-owned texture, typically sampled by a host renderer.
+    /// It needs `RENDER_ATTACHMENT` usage, and if its format is sRGB the
+    /// non-sRGB variant must be listed in its `view_formats`.
+    Texture(wgpu::Texture),
+}
+
+/// A texture lent to the engine as a backing store. Returned to its pool by
+/// `destroy_backing_store_callback` once the engine releases it.
+#[derive(Debug)]
+struct BackingStore {
+    texture: wgpu::Texture,
+    image: FlutterVulkanImage,
+    /// Where the texture goes once the engine released it.
+    pool: BackingStorePool,
+}
+
+/// A layer of the last frame presented by the engine: a backing store or a
+/// platform view. Generic over the texture so damage can be computed without
+/// a device.
+#[derive(Clone, Debug)]
+struct CompositorLayer<T = wgpu::Texture> {
+    texture: T,
+    /// Maps the unit quad onto the target, in pixels.
+    transform: Mat4,
+    opacity: f32,
+    clip: Option<PixelRect>,
+    /// The bounds of the part of the layer with content, the engine's paint
+    /// region, in target pixels. Outside of it the layer is transparent.
+    /// `None` if all of it may have content.
+    content: Option<PixelRect>,
+    /// Whether the texture changed since the layer was last presented.
+    updated: bool,
+}
+
+impl<T: PartialEq> CompositorLayer<T> {
+    /// The target pixels the layer can change.
+    fn visible_bounds(&self) -> PixelRect {
+        let bounds = self
+            .content
+            .unwrap_or_else(|| PixelRect::transformed_bounds(&self.transform, &UNIT_RECT));
+        self.clip.map_or(bounds, |clip| bounds.intersect(&clip))
+    }
+
+    /// Whether `self` shows the same texture in the same place as `other`.
+    fn same_placement(&self, other: &Self) -> bool {
+        self.texture == other.texture
+            && self.transform == other.transform
+            && self.opacity == other.opacity
+            && self.clip == other.clip
+    }
+}
+
+/// The part of the target that changed since the previous frame.
+///
+/// The embedder API reports no damage within Vulkan backing stores:
+/// `populate_existing_damage` and `FlutterPresentInfo::frame_damage` only exist
+/// for OpenGL. So a backing store the engine updated damages the whole region
+/// it painted, and only texture targets, which keep their pixels between
+/// frames, are drawn partially.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Damage {
+    Nothing,
+    Rect(PixelRect),
+    Full,
+}
+
+impl Damage {
+    /// Compares the layers of a frame with those of the frame before. Layers
+    /// that moved or whose texture the engine updated damage their old and
+    /// new bounds; a different number of layers damages everything.
+    fn between<T: PartialEq>(
+        previous: &[CompositorLayer<T>],
+        layers: &[CompositorLayer<T>],
+    ) -> Self {
+        if previous.len() != layers.len() {
+            return Damage::Full;
+        }
+        previous
+            .iter()
+            .zip(layers)
+            .filter(|(old, new)| new.updated || !old.same_placement(new))
+            .fold(Damage::Nothing, |damage, (old, new)| {
+                damage.add(old.visible_bounds()).add(new.visible_bounds())
+            })
+    }
+
+    fn add(self, rect: PixelRect) -> Self {
+        if rect.right <= rect.left || rect.bottom <= rect.top {
+            return self;
+        }
+        match self {
+            Damage::Nothing => Damage::Rect(rect),
+            Damage::Rect(damage) => Damage::Rect(damage.union(&rect)),
+            Damage::Full => Damage::Full,
+        }
+    }
+}
+
+/// An axis aligned rect in target pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PixelRect {
+    pub(crate) left: f32,
+    pub(crate) top: f32,
+    pub(crate) right: f32,
+    pub(crate) bottom: f32,
+}
+
+impl PixelRect {
+    /// The bounding box of `rect` after `transform`.
+    pub(crate) fn transformed_bounds(transform: &Mat4, rect: &FlutterRect) -> Self {
+        let corners = [
+            (rect.left, rect.top),
+            (rect.right, rect.top),
+            (rect.right, rect.bottom),
+            (rect.left, rect.bottom),
+        ]
+        .map(|(x, y)| transform_point(transform, x as f32, y as f32));
+        corners.iter().fold(
+            Self {
+                left: f32::INFINITY,
+                top: f32::INFINITY,
+                right: f32::NEG_INFINITY,
+                bottom: f32::NEG_INFINITY,
+            },
+            |bounds, &(x, y)| Self {
+                left: bounds.left.min(x),
+                top: bounds.top.min(y),
+                right: bounds.right.max(x),
+                bottom: bounds.bottom.max(y),
+            },
+        )
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        Self {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn intersect(&self, other: &Self) -> Self {
+        Self {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        }
+    }
+
+    pub(crate) fn contains(&self, x: f32, y: f32) -> bool {
+        (self.left..=self.right).contains(&x) && (self.top..=self.bottom).contains(&y)
+    }
+
+    /// The pixels covered by the rect within a `target` sized render target,
+    /// as a scissor rect, or `None` if it covers none.
+    fn scissor(&self, target: winit::dpi::PhysicalSize<u32>) -> Option<(u32, u32, u32, u32)> {
+        let left = self.left.floor().clamp(0.0, target.width as f32) as u32;
+        let top = self.top.floor().clamp(0.0, target.height as f32) as u32;
+        let right = self.right.ceil().clamp(0.0, target.width as f32) as u32;
+        let bottom = self.bottom.ceil().clamp(0.0, target.height as f32) as u32;
+        (right > left && bottom > top).then(|| (left, top, right - left, bottom - top))
+    }
+}
+
+#[derive(Debug)]
+struct LayerPipeline {
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    /// Clears the scissor rect before a partial repaint.
+    clear_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl LayerPipeline {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/composite.wgsl"));
+        let constants = HashMap::from([(
+            "decode_srgb".to_owned(),
+            if surface::is_linear(format) { 1.0 } else { 0.0 },
+        )]);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("flutter layer"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("flutter layer"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("flutter layer"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let clear_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("flutter layer clear"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+        let clear_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("flutter layer clear"),
+            layout: Some(&clear_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_clear"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_clear"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("flutter layer"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            format,
+            pipeline,
+            clear_pipeline,
+            bind_group_layout,
+            sampler,
+        }
+    }
+}
+
+/// The quad that layer transforms map onto the target.
+const UNIT_RECT: FlutterRect = FlutterRect {
+    left: 0.0,
+    top: 0.0,
+    right: 1.0,
+    bottom: 1.0,
 };
 
+/// A column major matrix, as in WGSL.
+pub(crate) type Mat4 = [[f32; 4]; 4];
+
+pub(crate) const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub(crate) fn mat4_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut product = [[0.0; 4]; 4];
+    for (column, b_column) in product.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    product
+}
+
+/// Maps the unit quad onto the `offset`/`size` pixel rect.
+fn rect_transform(offset: (f32, f32), size: (f32, f32)) -> Mat4 {
+    [
+        [size.0, 0.0, 0.0, 0.0],
+        [0.0, size.1, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [offset.0, offset.1, 0.0, 1.0],
+    ]
+}
+
+/// Maps the pixels of a `target` sized render target to clip space.
+fn pixel_to_clip(target: (f32, f32)) -> Mat4 {
+    [
+        [2.0 / target.0, 0.0, 0.0, 0.0],
+        [0.0, -2.0 / target.1, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0, 1.0],
+    ]
+}
+
+pub(crate) fn flutter_transformation(transformation: &FlutterTransformation) -> Mat4 {
+    let t = transformation;
+    [
+        [t.scaleX as f32, t.skewY as f32, 0.0, t.pers0 as f32],
+        [t.skewX as f32, t.scaleY as f32, 0.0, t.pers1 as f32],
+        [0.0, 0.0, 1.0, 0.0],
+        [t.transX as f32, t.transY as f32, 0.0, t.pers2 as f32],
+    ]
+}
+
+fn transform_point(transform: &Mat4, x: f32, y: f32) -> (f32, f32) {
+    let m = transform;
+    let w = m[0][3] * x + m[1][3] * y + m[3][3];
+    (
+        (m[0][0] * x + m[1][0] * y + m[3][0]) / w,
+        (m[0][1] * x + m[1][1] * y + m[3][1]) / w,
+    )
+}
+
+/// Solves for the size of a rect at the origin whose bounding box under the
+/// linear part of `transform` is `bounds`. `None` if that is ambiguous, e.g.
+/// for a rotation by 45 degrees.
+fn untransformed_size(transform: &Mat4, bounds: (f32, f32)) -> Option<(f32, f32)> {
+    let (a, b) = (transform[0][0].abs(), transform[1][0].abs());
+    let (c, d) = (transform[0][1].abs(), transform[1][1].abs());
+    let det = a * d - b * c;
+    if det.abs() < 1e-4 {
+        return None;
+    }
+    let width = (d * bounds.0 - b * bounds.1) / det;
+    let height = (a * bounds.1 - c * bounds.0) / det;
+    (width > 0.0 && height > 0.0).then_some((width, height))
+}
+
+/// The bounds of the part of a backing store layer the engine painted, in
+/// target pixels, or `None` if the engine did not say.
+fn paint_region_bounds(layer: &FlutterLayer) -> Option<PixelRect> {
+    let info = unsafe { layer.backing_store_present_info.as_ref() }?;
+    let region = unsafe { info.paint_region.as_ref() }?;
+    let rects = if region.rects.is_null() {
+        &[][..]
+    } else {
+        unsafe { std::slice::from_raw_parts(region.rects, region.rects_count) }
+    };
+    let (x, y) = (layer.offset.x as f32, layer.offset.y as f32);
+    let empty = PixelRect {
+        left: x,
+        top: y,
+        right: x,
+        bottom: y,
+    };
+    let bounds = rects
+        .iter()
+        .map(|rect| PixelRect {
+            left: x + rect.left as f32,
+            top: y + rect.top as f32,
+            right: x + rect.right as f32,
+            bottom: y + rect.bottom as f32,
+        })
+        .reduce(|bounds, rect| bounds.union(&rect));
+    Some(bounds.unwrap_or(empty))
+}
+
+/// The Vulkan image of `texture`, if wgpu runs on Vulkan.
+fn vulkan_image(texture: &wgpu::Texture) -> Option<u64> {
+    unsafe {
+        texture.as_hal::<wgpu_hal::api::Vulkan, _, _>(|texture| {
+            texture.map(|texture| texture.raw_handle().as_raw())
+        })
+    }
+}
+
+fn layer_uniforms(transform: &Mat4, opacity: f32) -> [u8; LAYER_UNIFORMS_SIZE] {
+    let mut bytes = [0u8; LAYER_UNIFORMS_SIZE];
+    let values = transform.iter().flatten().chain(std::iter::once(&opacity));
+    for (chunk, value) in bytes.chunks_exact_mut(4).zip(values) {
+        chunk.copy_from_slice(&value.to_ne_bytes());
+    }
+    bytes
+}
+
 #[derive(Debug)]
 pub struct Compositor {
     instance: wgpu::Instance,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    surface: wgpu::Surface<'static>,
+    target: CompositorTarget,
     surface_format: wgpu::TextureFormat,
     surface_size: winit::dpi::PhysicalSize<u32>,
+    /// How a window surface is configured again on resize, see
+    /// [`surface::SurfaceOptions`].
+    surface_config: Option<wgpu::SurfaceConfiguration>,
+    /// The window of a surface target, to create the surface again if it is lost.
+    surface_window: Option<Arc<winit::window::Window>>,
     present_surface_texture: Option<wgpu::SurfaceTexture>,
+    layer_pipeline: LayerPipeline,
+    layers: Vec<CompositorLayer>,
+    platform_views: PlatformViewRegistry,
+    view_id: FlutterViewId,
+    gpu_timer: Option<GpuTimer>,
+    performance_overlay: Option<PerformanceOverlay>,
+    /// Set when the target lost its pixels or their meaning, e.g. after a
+    /// resize, so the next frame is drawn in full.
+    full_repaint: bool,
 }
 
 impl Compositor {
@@ -26,24 +512,131 @@ impl Compositor {
         instance: wgpu::Instance,
         device: wgpu::Device,
         queue: wgpu::Queue,
+        window: Arc<winit::window::Window>,
         surface: wgpu::Surface<'static>,
-        surface_format: wgpu::TextureFormat,
-        surface_size: winit::dpi::PhysicalSize<u32>,
+        surface_config: wgpu::SurfaceConfiguration,
     ) -> Self {
-        let mut instance = Compositor {
-            instance: instance,
-            device: device,
-            queue: queue,
-            surface: surface,
-            surface_format: surface_format,
-            surface_size: surface_size,
-            present_surface_texture: None,
-        };
+        let surface_size =
+            winit::dpi::PhysicalSize::new(surface_config.width, surface_config.height);
+        let mut instance = Self::with_target(
+            instance,
+            device,
+            queue,
+            CompositorTarget::Surface(surface),
+            surface_config.format,
+        );
 
+        instance.surface_config = Some(surface_config);
+        instance.surface_window = Some(window);
         instance.resize(surface_size);
         instance
     }
 
+    /// Creates a compositor that draws into `texture` instead of a window surface.
+    pub fn new_with_texture(
+        instance: wgpu::Instance,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        texture: wgpu::Texture,
+    ) -> Self {
+        let format = texture.format();
+        let size = winit::dpi::PhysicalSize::new(texture.width(), texture.height());
+        let mut instance = Self::with_target(
+            instance,
+            device,
+            queue,
+            CompositorTarget::Texture(texture),
+            format,
+        );
+
+        instance.surface_size = size;
+        instance
+    }
+
+    fn with_target(
+        instance: wgpu::Instance,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: CompositorTarget,
+        surface_format: wgpu::TextureFormat,
+    ) -> Self {
+        // Flutter renders sRGB encoded pixels into non-sRGB backing stores, so the
+        // target is viewed without the sRGB suffix to copy them through unchanged.
+        let layer_pipeline = LayerPipeline::new(&device, surface_format.remove_srgb_suffix());
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        Compositor {
+            instance,
+            device,
+            queue,
+            target,
+            surface_format,
+            surface_size: winit::dpi::PhysicalSize::new(0, 0),
+            surface_config: None,
+            surface_window: None,
+            present_surface_texture: None,
+            layer_pipeline,
+            layers: Vec::new(),
+            platform_views: PlatformViewRegistry::default(),
+            view_id: IMPLICIT_VIEW_ID,
+            gpu_timer,
+            performance_overlay: None,
+            full_repaint: true,
+        }
+    }
+
+    /// Draws graphs of the recent frame times on top of the Flutter layers,
+    /// see [`FrameStats`].
+    pub fn set_performance_overlay(&mut self, enabled: bool) {
+        self.full_repaint = true;
+        self.performance_overlay =
+            enabled.then(|| PerformanceOverlay::new(&self.device, self.layer_pipeline.format));
+    }
+
+    fn record_frame(&mut self, stats: &FrameStats, budget: Option<Duration>) {
+        if let Some(overlay) = &mut self.performance_overlay {
+            overlay.record(stats, budget);
+        }
+    }
+
+    /// Sets the registry that renders the platform view layers.
+    pub(crate) fn set_platform_views(&mut self, platform_views: PlatformViewRegistry) {
+        self.platform_views = platform_views;
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.surface_size
+    }
+
+    /// The texture the compositor draws into, if it is not drawing to a surface.
+    pub fn target_texture(&self) -> Option<&wgpu::Texture> {
+        match &self.target {
+            CompositorTarget::Texture(texture) => Some(texture),
+            CompositorTarget::Surface(_) => None,
+        }
+    }
+
+    /// Replaces the target texture, e.g. after the host resized its panel.
+    /// Does nothing if the compositor draws to a surface.
+    pub fn set_target_texture(&mut self, texture: wgpu::Texture) {
+        let CompositorTarget::Texture(target) = &mut self.target else {
+            error!("set_target_texture called on a surface compositor");
+            return;
+        };
+        self.surface_size = winit::dpi::PhysicalSize::new(texture.width(), texture.height());
+        self.surface_format = texture.format();
+        if self.layer_pipeline.format != self.surface_format.remove_srgb_suffix() {
+            self.layer_pipeline =
+                LayerPipeline::new(&self.device, self.surface_format.remove_srgb_suffix());
+        }
+        if let Some(overlay) = &mut self.performance_overlay {
+            if overlay.format() != self.layer_pipeline.format {
+                *overlay = PerformanceOverlay::new(&self.device, self.layer_pipeline.format);
+            }
+        }
+        *target = texture;
+        self.full_repaint = true;
+    }
+
     #[instrument(level = "info", skip(self))]
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width == 0 || new_size.height == 0 {
@@ -53,62 +646,217 @@ impl Compositor {
         {
             return;
         }
+        let (CompositorTarget::Surface(_), Some(surface_config)) =
+            (&self.target, &mut self.surface_config)
+        else {
+            return;
+        };
         self.surface_size = new_size;
 
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: self.surface_format,
-            // Request compatibility with the sRGB-format texture view we‘re going to create later.
-            view_formats: vec![self.surface_format.add_srgb_suffix()],
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            width: self.surface_size.width,
-            height: self.surface_size.height,
-            desired_maximum_frame_latency: 1,
-            present_mode: wgpu::PresentMode::Fifo,
+        surface_config.width = new_size.width;
+        surface_config.height = new_size.height;
+        self.configure_surface();
+    }
+
+    fn configure_surface(&mut self) {
+        // Reconfiguring panics while a texture of the surface is alive.
+        self.present_surface_texture = None;
+        if let (CompositorTarget::Surface(surface), Some(surface_config)) =
+            (&self.target, &self.surface_config)
+        {
+            surface.configure(&self.device, surface_config);
+        }
+        self.full_repaint = true;
+    }
+
+    /// Replaces a lost surface with a new one for the same window.
+    fn recreate_surface(&mut self) -> bool {
+        let Some(window) = self.surface_window.clone() else {
+            return false;
         };
+        match self.instance.create_surface(window) {
+            Ok(surface) => {
+                self.present_surface_texture = None;
+                self.target = CompositorTarget::Surface(surface);
+                true
+            }
+            Err(error) => {
+                error!("Failed to recreate the lost surface: {}", error);
+                false
+            }
+        }
+    }
 
-        self.surface.configure(&self.device, &surface_config);
+    /// The next texture of the window surface, or `None` to skip the frame. An
+    /// outdated surface is configured again and a lost one recreated first.
+    fn acquire_surface_texture(&mut self) -> Option<wgpu::SurfaceTexture> {
+        let CompositorTarget::Surface(surface) = &self.target else {
+            return None;
+        };
+        match surface.get_current_texture() {
+            Ok(surface_texture) => return Some(surface_texture),
+            Err(wgpu::SurfaceError::Timeout) => {
+                debug!("Timed out acquiring a surface texture, skipping the frame");
+                return None;
+            }
+            Err(wgpu::SurfaceError::Outdated) => {
+                debug!("The surface is outdated, configuring it again");
+            }
+            Err(wgpu::SurfaceError::Lost) => {
+                warn!("The surface was lost, creating it again");
+                if !self.recreate_surface() {
+                    return None;
+                }
+            }
+            // A lost device is replaced by the app, see `App::recover_device`.
+            Err(error @ (wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other)) => {
+                error!("Failed to acquire a surface texture: {}", error);
+                return None;
+            }
+        }
+        self.configure_surface();
+        let CompositorTarget::Surface(surface) = &self.target else {
+            return None;
+        };
+        surface
+            .get_current_texture()
+            .inspect_err(|error| warn!("Failed to acquire a surface texture: {}", error))
+            .ok()
     }
+
+    /// Draws the layers of the last presented frame into the target.
     #[instrument(level = "debug", skip(self))]
     pub fn render(&mut self) {
-        let Ok(surface_texture) = self.surface.get_current_texture() else {
-            return;
+        self.composite(None);
+    }
+
+    /// Draws the layers into `region` of the target, or into all of it, and
+    /// returns whether it drew. Outside of `region` the target keeps its pixels,
+    /// so only texture targets, whose contents persist, can be drawn in part.
+    fn composite(&mut self, region: Option<PixelRect>) -> bool {
+        let (target_texture, surface_texture) = match &self.target {
+            CompositorTarget::Surface(_) => {
+                let Some(surface_texture) = self.acquire_surface_texture() else {
+                    return false;
+                };
+                (surface_texture.texture.clone(), Some(surface_texture))
+            }
+            CompositorTarget::Texture(texture) => (texture.clone(), None),
         };
 
-        let texture_view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor {
-                // Without add_srgb_suffix() the image we will be working with
-                // might not be "gamma correct".
-                format: Some(self.surface_format.add_srgb_suffix()),
-                ..Default::default()
-            });
+        let texture_view = target_texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.layer_pipeline.format),
+            ..Default::default()
+        });
+
+        let target_size = (
+            self.surface_size.width as f32,
+            self.surface_size.height as f32,
+        );
+        let full_target = PixelRect {
+            left: 0.0,
+            top: 0.0,
+            right: target_size.0,
+            bottom: target_size.1,
+        };
+        let partial = region.is_some();
+        let region = region.map_or(full_target, |region| region.intersect(&full_target));
+        let pixel_to_clip = pixel_to_clip(target_size);
+        let draws = self
+            .layers
+            .iter()
+            .filter_map(|layer| {
+                let scissor = layer
+                    .clip
+                    .map_or(region, |clip| clip.intersect(&region))
+                    .scissor(self.surface_size)?;
+                let transform = mat4_mul(&pixel_to_clip, &layer.transform);
+                let uniforms = self
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("flutter layer"),
+                        contents: &layer_uniforms(&transform, layer.opacity),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+                let layer_view = layer.texture.create_view(&Default::default());
+                let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("flutter layer"),
+                    layout: &self.layer_pipeline.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: uniforms.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&layer_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Sampler(&self.layer_pipeline.sampler),
+                        },
+                    ],
+                });
+                Some((bind_group, scissor))
+            })
+            .collect::<Vec<_>>();
 
+        let timestamp_writes = self
+            .gpu_timer
+            .as_mut()
+            .and_then(|timer| timer.begin(&self.device));
+        let measured = timestamp_writes.is_some();
         let mut encoder = self.device.create_command_encoder(&Default::default());
-        // Create the renderpass which will clear the screen.
-        let renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &texture_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                    load: if partial {
+                        wgpu::LoadOp::Load
+                    } else {
+                        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                    },
                     store: wgpu::StoreOp::Store,
                 },
             })],
             depth_stencil_attachment: None,
-            timestamp_writes: None,
+            timestamp_writes,
             occlusion_query_set: None,
         });
 
-        // If you wanted to call any drawing commands, they would go here.
+        if let Some((x, y, width, height)) = region.scissor(self.surface_size).filter(|_| partial) {
+            renderpass.set_pipeline(&self.layer_pipeline.clear_pipeline);
+            renderpass.set_scissor_rect(x, y, width, height);
+            renderpass.draw(0..4, 0..1);
+        }
+        renderpass.set_pipeline(&self.layer_pipeline.pipeline);
+        for (bind_group, (x, y, width, height)) in &draws {
+            renderpass.set_scissor_rect(*x, *y, *width, *height);
+            renderpass.set_bind_group(0, bind_group, &[]);
+            renderpass.draw(0..4, 0..1);
+        }
+        if let Some(overlay) = &self.performance_overlay {
+            overlay.draw(&self.device, &mut renderpass, target_size);
+        }
 
-        // End the renderpass.
         drop(renderpass);
 
+        let gpu_timer = self.gpu_timer.as_mut().filter(|_| measured);
+        if let Some(timer) = &gpu_timer {
+            timer.resolve(&mut encoder);
+        }
         self.queue.submit([encoder.finish()]);
+        if let Some(timer) = gpu_timer {
+            timer.submitted();
+        }
 
-        self.present_surface_texture = Some(surface_texture);
+        self.present_surface_texture = surface_texture;
+        if !partial {
+            self.full_repaint = false;
+        }
+        true
     }
 
     pub fn present(&mut self) {
@@ -117,23 +865,248 @@ impl Compositor {
         }
     }
 
-    pub fn get_flutter_compositor(&mut self) -> FlutterCompositor {
+    fn present_layers(&mut self, layers: &[*const FlutterLayer]) -> bool {
+        let mut placements = Vec::new();
+        let previous = std::mem::take(&mut self.layers);
+        self.layers = layers
+            .iter()
+            .filter_map(|&layer| {
+                let layer = unsafe { &*layer };
+                if layer.type_ == FlutterLayerContentType_kFlutterLayerContentTypePlatformView {
+                    let view = unsafe { &**layer.__bindgen_anon_1.platform_view.as_ref() };
+                    return self.platform_view_layer(layer, view, &mut placements);
+                }
+                if layer.type_ != FlutterLayerContentType_kFlutterLayerContentTypeBackingStore {
+                    debug!("skipping layer of unknown type {}", layer.type_);
+                    return None;
+                }
+                let flutter_backing_store =
+                    unsafe { &**layer.__bindgen_anon_1.backing_store.as_ref() };
+                let backing_store =
+                    unsafe { &*(flutter_backing_store.user_data as *const BackingStore) };
+                Some(CompositorLayer {
+                    texture: backing_store.texture.clone(),
+                    transform: rect_transform(
+                        (layer.offset.x as f32, layer.offset.y as f32),
+                        (layer.size.width as f32, layer.size.height as f32),
+                    ),
+                    opacity: 1.0,
+                    clip: None,
+                    content: paint_region_bounds(layer),
+                    updated: flutter_backing_store.did_update,
+                })
+            })
+            .collect();
+        self.platform_views.set_placements(self.view_id, placements);
+
+        let damage = if self.full_repaint || self.performance_overlay.is_some() {
+            Damage::Full
+        } else {
+            Damage::between(&previous, &self.layers)
+        };
+        match (&self.target, damage) {
+            (_, Damage::Nothing) => {
+                debug!("view {} did not change, skipping the frame", self.view_id);
+                return true;
+            }
+            (CompositorTarget::Texture(_), Damage::Rect(rect)) => {
+                self.composite(Some(rect));
+            }
+            // wgpu exposes neither the age of swapchain images nor incremental
+            // present, so surfaces are drawn in full and only skipped when
+            // nothing changed.
+            _ => {
+                self.composite(None);
+            }
+        }
+        self.present();
+        true
+    }
+
+    /// Renders a platform view and places it with its mutations applied.
+    fn platform_view_layer(
+        &self,
+        layer: &FlutterLayer,
+        view: &FlutterPlatformView,
+        placements: &mut Vec<PlatformViewPlacement>,
+    ) -> Option<CompositorLayer> {
+        let mutations = if view.mutations.is_null() {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(view.mutations, view.mutations_count) }
+        };
+
+        // Mutations are applied in order, each in the space set up by the
+        // transformations before it.
+        let mut transform = IDENTITY;
+        let mut opacity = 1.0;
+        let mut clip: Option<PixelRect> = None;
+        for &mutation in mutations {
+            let mutation = unsafe { &*mutation };
+            let union = &mutation.__bindgen_anon_1;
+            let rect = if mutation.type_
+                == FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeOpacity
+            {
+                opacity *= unsafe { *union.opacity.as_ref() } as f32;
+                continue;
+            } else if mutation.type_
+                == FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeTransformation
+            {
+                let transformation = unsafe { union.transformation.as_ref() };
+                transform = mat4_mul(&transform, &flutter_transformation(transformation));
+                continue;
+            } else if mutation.type_
+                == FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRect
+            {
+                unsafe { *union.clip_rect.as_ref() }
+            } else if mutation.type_
+                == FlutterPlatformViewMutationType_kFlutterPlatformViewMutationTypeClipRoundedRect
+            {
+                // Only scissor clipping is available, so the corners are not rounded.
+                unsafe { union.clip_rounded_rect.as_ref().rect }
+            } else {
+                continue;
+            };
+            let bounds = PixelRect::transformed_bounds(&transform, &rect);
+            clip = Some(clip.map_or(bounds, |clip| clip.intersect(&bounds)));
+        }
+
+        // The layer rect is the bounding box of the transformed view. Recover
+        // the view's own size from it, and keep the view on the layer offset.
+        let offset = (layer.offset.x as f32, layer.offset.y as f32);
+        let layer_size = (layer.size.width as f32, layer.size.height as f32);
+        let transform = match untransformed_size(&transform, layer_size) {
+            Some(view_size) => {
+                let transform = mat4_mul(&transform, &rect_transform((0.0, 0.0), view_size));
+                let bounds = PixelRect::transformed_bounds(&transform, &UNIT_RECT);
+                let correction =
+                    rect_transform((offset.0 - bounds.left, offset.1 - bounds.top), (1.0, 1.0));
+                mat4_mul(&correction, &transform)
+            }
+            None => rect_transform(offset, layer_size),
+        };
+
+        let size = winit::dpi::PhysicalSize::new(
+            (layer.size.width.round() as u32).max(1),
+            (layer.size.height.round() as u32).max(1),
+        );
+        placements.push(PlatformViewPlacement {
+            id: view.identifier,
+            transform,
+            size,
+            clip,
+        });
+        let texture =
+            self.platform_views
+                .render(view.identifier, size, &self.device, &self.queue)?;
+        Some(CompositorLayer {
+            texture,
+            transform,
+            opacity,
+            clip,
+            content: None,
+            // Platform views render every frame.
+            updated: true,
+        })
+    }
+}
+
+/// The compositors of every view of an engine.
+///
+/// Its address is the user data of the engine's compositor callbacks: backing
+/// stores are shared by all views, and presented layers are routed to the
+/// compositor of their view.
+#[derive(Debug)]
+pub(crate) struct ViewCompositors {
+    instance: wgpu::Instance,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    views: Mutex<HashMap<FlutterViewId, Compositor>>,
+    frame_timer: Mutex<FrameTimer>,
+    backing_store_pool: Mutex<BackingStorePool>,
+    /// Whether views get the performance overlay when they are inserted.
+    performance_overlay: bool,
+}
+
+impl ViewCompositors {
+    /// Starts with the compositor of the implicit view, whose GPU context all views share.
+    pub(crate) fn new(implicit_view: Compositor, performance_overlay: bool) -> Self {
+        let view_compositors = Self {
+            instance: implicit_view.instance.clone(),
+            device: implicit_view.device.clone(),
+            queue: implicit_view.queue.clone(),
+            views: Mutex::new(HashMap::new()),
+            frame_timer: Mutex::new(FrameTimer::default()),
+            backing_store_pool: Mutex::new(BackingStorePool::default()),
+            performance_overlay,
+        };
+        view_compositors.insert(IMPLICIT_VIEW_ID, implicit_view);
+        view_compositors
+    }
+
+    pub(crate) fn insert(&self, view_id: FlutterViewId, mut compositor: Compositor) {
+        compositor.view_id = view_id;
+        if self.performance_overlay && compositor.performance_overlay.is_none() {
+            compositor.set_performance_overlay(true);
+        }
+        self.views.lock().unwrap().insert(view_id, compositor);
+    }
+
+    pub(crate) fn remove(&self, view_id: FlutterViewId) -> Option<Compositor> {
+        let compositor = self.views.lock().unwrap().remove(&view_id)?;
+        compositor.platform_views.remove_placements(view_id);
+        Some(compositor)
+    }
+
+    /// Runs `f` with the compositor of `view_id`, if the view exists.
+    pub(crate) fn with_view<R>(
+        &self,
+        view_id: FlutterViewId,
+        f: impl FnOnce(&mut Compositor) -> R,
+    ) -> Option<R> {
+        self.views.lock().unwrap().get_mut(&view_id).map(f)
+    }
+
+    pub(crate) fn frame_stats(&self) -> FrameStatsStream {
+        self.frame_timer.lock().unwrap().stream()
+    }
+
+    pub(crate) fn set_frame_stats(&self, frame_stats: FrameStatsStream) {
+        self.frame_timer.lock().unwrap().set_stream(frame_stats);
+    }
+
+    pub(crate) fn backing_store_pool(&self) -> BackingStorePool {
+        self.backing_store_pool.lock().unwrap().clone()
+    }
+
+    /// Keeps the textures of released backing stores in `pool` instead. Stores
+    /// lent before return to the pool they came from.
+    pub(crate) fn set_backing_store_pool(&self, pool: BackingStorePool) {
+        *self.backing_store_pool.lock().unwrap() = pool;
+    }
+
+    /// Records a vsync reported to the engine, for the timing of the next frames.
+    pub(crate) fn vsync(&self, interval: Duration) {
+        self.frame_timer.lock().unwrap().vsync(interval);
+    }
+
+    pub fn get_flutter_compositor(&self) -> FlutterCompositor {
         FlutterCompositor {
             struct_size: size_of::<FlutterCompositor>(),
-            user_data: as_void_ptr(self),
+            user_data: self as *const Self as *mut ::core::ffi::c_void,
             create_backing_store_callback: Some(Self::create_backing_store_callback),
             collect_backing_store_callback: Some(Self::collect_backing_store_callback),
-            present_layers_callback: Some(Self::present_layers_callback),
-            present_view_callback: None,
+            present_layers_callback: None,
+            present_view_callback: Some(Self::present_view_callback),
             avoid_backing_store_cache: false,
         }
     }
 
-    pub fn get_flutter_renderer_config(&mut self) -> FlutterRendererConfigWrapper {
-        return create_flutter_renderer_config(&self.instance, &self.device);
+    pub fn get_flutter_renderer_config(&self) -> Result<FlutterRendererConfigWrapper, AppError> {
+        create_flutter_renderer_config(&self.instance, &self.device)
     }
 
-    pub fn get_instance_proc_address_callback(&mut self) -> *mut ::core::ffi::c_void {
+    pub fn get_instance_proc_address_callback(&self) -> *mut ::core::ffi::c_void {
         let res = unsafe {
             self.instance
                 .as_hal::<wgpu_hal::api::Vulkan>()
@@ -141,35 +1114,285 @@ impl Compositor {
                     let shared_instance = instance.shared_instance();
                     let entry = shared_instance.entry();
                     let get_instance_proc_addr = entry.static_fn().get_instance_proc_addr;
-                    let ptr = get_instance_proc_addr as *mut ::core::ffi::c_void;
-                    ptr
+                    get_instance_proc_addr as *mut ::core::ffi::c_void
                 })
         };
-        res.unwrap()
+        res.unwrap_or_else(|| {
+            // The engine then fails to initialize, which `initialize` reports.
+            error!("The wgpu instance is not backed by Vulkan");
+            std::ptr::null_mut()
+        })
     }
 
-    extern "C" fn present_layers_callback(
-        layers: *mut *const FlutterLayer,
-        layers_count: usize,
-        user_data: *mut ::core::ffi::c_void,
+    /// Creates a texture for backing stores matching `key`, or `None` if it
+    /// cannot be lent to the engine.
+    fn new_backing_store_texture(&self, key: &TextureKey) -> Option<wgpu::Texture> {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("flutter backing store"),
+            size: wgpu::Extent3d {
+                width: key.width,
+                height: key.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: key.format,
+            usage: key.usage,
+            view_formats: &[],
+        });
+        if vulkan_image(&texture).is_none() {
+            error!("backing store texture is not backed by a Vulkan image");
+            return None;
+        }
+
+        // wgpu lazily zeroes textures it has not written yet, which would wipe
+        // what the engine rendered the first time we sample it. Clearing once
+        // here marks the texture as initialized.
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("flutter backing store clear"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &texture.create_view(&Default::default()),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        self.queue.submit([encoder.finish()]);
+        Some(texture)
+    }
+
+    fn create_backing_store(
+        &self,
+        config: &FlutterBackingStoreConfig,
+        backing_store_out: &mut FlutterBackingStore,
     ) -> bool {
+        let key = TextureKey {
+            width: (config.size.width as u32).max(1),
+            height: (config.size.height as u32).max(1),
+            format: BACKING_STORE_FORMAT,
+            usage: BACKING_STORE_USAGE,
+        };
+        let pool = self.backing_store_pool.lock().unwrap().clone();
+        let texture = match pool.take(&key) {
+            Some(texture) => texture,
+            None => {
+                let Some(texture) = self.new_backing_store_texture(&key) else {
+                    return false;
+                };
+                pool.allocated();
+                texture
+            }
+        };
+        let Some(image) = vulkan_image(&texture) else {
+            error!("backing store texture is not backed by a Vulkan image");
+            pool.recycle(texture);
+            return false;
+        };
+
+        self.frame_timer.lock().unwrap().backing_store_created();
+        let backing_store = Box::into_raw(Box::new(BackingStore {
+            texture,
+            pool,
+            image: FlutterVulkanImage {
+                struct_size: size_of::<FlutterVulkanImage>(),
+                image,
+                format: BACKING_STORE_VK_FORMAT.as_raw() as u32,
+            },
+        }));
+
+        backing_store_out.struct_size = size_of::<FlutterBackingStore>();
+        backing_store_out.user_data = backing_store as *mut ::core::ffi::c_void;
+        backing_store_out.type_ = FlutterBackingStoreType_kFlutterBackingStoreTypeVulkan;
+        backing_store_out.did_update = true;
+        let vulkan = unsafe { backing_store_out.__bindgen_anon_1.vulkan.as_mut() };
+        *vulkan = FlutterVulkanBackingStore {
+            struct_size: size_of::<FlutterVulkanBackingStore>(),
+            image: unsafe { &(*backing_store).image },
+            user_data: backing_store as *mut ::core::ffi::c_void,
+            destruction_callback: Some(Self::destroy_backing_store_callback),
+        };
         true
     }
 
+    extern "C" fn present_view_callback(info: *const FlutterPresentViewInfo) -> bool {
+        catch_callback_panic("present_view_callback", false, || {
+            let info = unsafe { &*info };
+            let view_compositors = unsafe { &*(info.user_data as *const ViewCompositors) };
+            let layers = unsafe { std::slice::from_raw_parts(info.layers, info.layers_count) };
+            let present_started = Instant::now();
+            let presented = view_compositors.with_view(info.view_id, |compositor| {
+                let presented = compositor.present_layers(layers);
+                if presented {
+                    let mut frame_timer = view_compositors.frame_timer.lock().unwrap();
+                    let gpu_duration = compositor.gpu_timer.as_ref().and_then(GpuTimer::latest);
+                    let stats = frame_timer.presented(info.view_id, present_started, gpu_duration);
+                    compositor.record_frame(&stats, frame_timer.interval());
+                }
+                presented
+            });
+            presented.unwrap_or_else(|| {
+                debug!("dropping frame for removed view {}", info.view_id);
+                false
+            })
+        })
+    }
+
     extern "C" fn create_backing_store_callback(
         config: *const FlutterBackingStoreConfig,
         backing_store_out: *mut FlutterBackingStore,
         user_data: *mut ::core::ffi::c_void,
     ) -> bool {
-        let compositor = unsafe { &*(user_data as *const Compositor) };
-
-        true
+        catch_callback_panic("create_backing_store_callback", false, || {
+            let view_compositors = unsafe { &*(user_data as *const ViewCompositors) };
+            let config = unsafe { &*config };
+            let backing_store_out = unsafe { &mut *backing_store_out };
+            view_compositors.create_backing_store(config, backing_store_out)
+        })
     }
 
     extern "C" fn collect_backing_store_callback(
-        renderer: *const FlutterBackingStore,
+        _backing_store: *const FlutterBackingStore,
         user_data: *mut ::core::ffi::c_void,
     ) -> bool {
-        true
+        catch_callback_panic("collect_backing_store_callback", false, || {
+            let view_compositors = unsafe { &*(user_data as *const ViewCompositors) };
+            view_compositors
+                .frame_timer
+                .lock()
+                .unwrap()
+                .backing_store_collected();
+            // The texture is released in `destroy_backing_store_callback`, once the
+            // engine is done with the image as well.
+            true
+        })
+    }
+
+    extern "C" fn destroy_backing_store_callback(user_data: *mut ::core::ffi::c_void) {
+        catch_callback_panic("destroy_backing_store_callback", (), || {
+            let backing_store = unsafe { Box::from_raw(user_data as *mut BackingStore) };
+            backing_store.pool.recycle(backing_store.texture);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: f32, top: f32, right: f32, bottom: f32) -> PixelRect {
+        PixelRect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// A layer showing texture `id` at `offset` with `size`.
+    fn layer(id: u32, offset: (f32, f32), size: (f32, f32)) -> CompositorLayer<u32> {
+        CompositorLayer {
+            texture: id,
+            transform: rect_transform(offset, size),
+            opacity: 1.0,
+            clip: None,
+            content: None,
+            updated: false,
+        }
+    }
+
+    #[test]
+    fn unchanged_layers_damage_nothing() {
+        let layers = [
+            layer(1, (0.0, 0.0), (100.0, 100.0)),
+            layer(2, (10.0, 10.0), (20.0, 20.0)),
+        ];
+        assert_eq!(Damage::between(&layers, &layers), Damage::Nothing);
+        assert_eq!(Damage::between::<u32>(&[], &[]), Damage::Nothing);
+    }
+
+    #[test]
+    fn changed_layers_damage_the_union_of_their_bounds() {
+        let previous = [
+            layer(1, (0.0, 0.0), (100.0, 100.0)),
+            layer(2, (10.0, 10.0), (20.0, 20.0)),
+            layer(3, (50.0, 50.0), (10.0, 10.0)),
+        ];
+
+        let mut layers = previous.clone();
+        layers[1].updated = true;
+        assert_eq!(
+            Damage::between(&previous, &layers),
+            Damage::Rect(rect(10.0, 10.0, 30.0, 30.0))
+        );
+
+        // A moved layer damages where it was and where it is now.
+        layers[2].transform = rect_transform((70.0, 60.0), (10.0, 10.0));
+        assert_eq!(
+            Damage::between(&previous, &layers),
+            Damage::Rect(rect(10.0, 10.0, 80.0, 70.0))
+        );
+
+        // Only the painted region of an updated layer is damaged.
+        let mut layers = previous.clone();
+        layers[0].updated = true;
+        layers[0].content = Some(rect(40.0, 0.0, 60.0, 5.0));
+        assert_eq!(
+            Damage::between(&previous, &layers),
+            Damage::Rect(rect(0.0, 0.0, 100.0, 100.0))
+        );
+        let mut previous = previous;
+        previous[0].content = Some(rect(40.0, 0.0, 60.0, 5.0));
+        assert_eq!(
+            Damage::between(&previous, &layers),
+            Damage::Rect(rect(40.0, 0.0, 60.0, 5.0))
+        );
+    }
+
+    #[test]
+    fn a_different_number_of_layers_damages_everything() {
+        let previous = [layer(1, (0.0, 0.0), (100.0, 100.0))];
+        let layers = [
+            layer(1, (0.0, 0.0), (100.0, 100.0)),
+            layer(2, (0.0, 0.0), (1.0, 1.0)),
+        ];
+        assert_eq!(Damage::between(&previous, &layers), Damage::Full);
+        assert_eq!(Damage::between(&layers, &previous), Damage::Full);
+        assert_eq!(Damage::between(&[], &previous), Damage::Full);
+    }
+
+    #[test]
+    fn zero_area_bounds_are_ignored() {
+        let previous = [
+            layer(1, (0.0, 0.0), (100.0, 100.0)),
+            layer(2, (10.0, 10.0), (20.0, 20.0)),
+        ];
+
+        // Nothing was painted into the updated layer.
+        let mut layers = previous.clone();
+        layers[0].updated = true;
+        layers[0].content = Some(rect(40.0, 40.0, 40.0, 60.0));
+        let mut old = previous.clone();
+        old[0].content = Some(rect(40.0, 40.0, 60.0, 40.0));
+        assert_eq!(Damage::between(&old, &layers), Damage::Nothing);
+
+        // A layer clipped away entirely.
+        let mut old = previous.clone();
+        old[1].clip = Some(rect(50.0, 50.0, 60.0, 60.0));
+        let mut layers = old.clone();
+        layers[1].updated = true;
+        assert_eq!(Damage::between(&old, &layers), Damage::Nothing);
+
+        // A layer scaled to nothing only damages where it was.
+        let mut layers = previous.clone();
+        layers[0].transform = rect_transform((0.0, 0.0), (0.0, 100.0));
+        assert_eq!(
+            Damage::between(&previous, &layers),
+            Damage::Rect(rect(0.0, 0.0, 100.0, 100.0))
+        );
     }
 }
@@ -0,0 +1,68 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use winit::dpi::PhysicalSize;
+use winit::monitor::MonitorHandle;
+use winit::window::Window;
+
+use crate::flutter_embedder::{FlutterEngineDisplay, FlutterEngineDisplayId};
+
+/// The refresh rate assumed when the monitor does not report one.
+pub(crate) const DEFAULT_REFRESH_RATE: f64 = 60.0;
+
+/// A monitor as reported to the engine. Its id is derived from the monitor's
+/// name and position, see [`display_id`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Display {
+    pub(crate) id: FlutterEngineDisplayId,
+    /// In frames per second, or zero when unknown.
+    pub(crate) refresh_rate: f64,
+    size: PhysicalSize<u32>,
+    scale_factor: f64,
+}
+
+/// The monitors available to `window`.
+pub(crate) fn enumerate(window: &Window) -> Vec<Display> {
+    window
+        .available_monitors()
+        .map(|monitor| Display {
+            id: display_id(&monitor),
+            refresh_rate: monitor
+                .refresh_rate_millihertz()
+                .map_or(0.0, |millihertz| millihertz as f64 / 1000.0),
+            size: monitor.size(),
+            scale_factor: monitor.scale_factor(),
+        })
+        .collect()
+}
+
+/// The id of the monitor that shows most of `window`.
+pub(crate) fn current_display(window: &Window) -> Option<FlutterEngineDisplayId> {
+    window.current_monitor().map(|monitor| display_id(&monitor))
+}
+
+/// An id that stays the same while the monitor is connected, unlike its
+/// index in the list of available monitors, which shifts when monitors are
+/// plugged in or out. It is a hash of the monitor's name and position.
+fn display_id(monitor: &MonitorHandle) -> FlutterEngineDisplayId {
+    let mut hasher = DefaultHasher::new();
+    monitor.name().hash(&mut hasher);
+    let position = monitor.position();
+    (position.x, position.y).hash(&mut hasher);
+    hasher.finish() as FlutterEngineDisplayId
+}
+
+pub(crate) fn to_flutter(displays: &[Display]) -> Vec<FlutterEngineDisplay> {
+    displays
+        .iter()
+        .map(|display| FlutterEngineDisplay {
+            struct_size: std::mem::size_of::<FlutterEngineDisplay>(),
+            display_id: display.id,
+            single_display: displays.len() == 1,
+            refresh_rate: display.refresh_rate,
+            width: display.size.width as usize,
+            height: display.size.height as usize,
+            device_pixel_ratio: display.scale_factor,
+        })
+        .collect()
+}
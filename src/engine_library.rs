@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use libloading::Library;
+use tracing::{error, info};
+
+use crate::application::AppError;
+use crate::flutter_embedder::{
+    FlutterEngineProcTable, FlutterEngineResult, FlutterEngineResult_kSuccess,
+};
+use crate::tracing_integration;
+
+/// Engine libraries that are currently loaded, so that every engine started
+/// from the same path shares one copy of the library and of the Dart VM.
+static LOADED_LIBRARIES: Mutex<Vec<Weak<EngineLibrary>>> = Mutex::new(Vec::new());
+
+/// A loaded Flutter engine library and its proc table.
+///
+/// Every session holds an `Arc` to the library its engine runs from, so the
+/// library is unloaded only after the last of those engines has shut down.
+/// Engines are started with `shutdown_dart_vm_when_done`, which stops the VM
+/// when the last engine of the process shuts down and makes unloading safe.
+#[derive(Debug)]
+pub(crate) struct EngineLibrary {
+    path: PathBuf,
+    library: Library,
+    procs: FlutterEngineProcTable,
+    running_engines: AtomicUsize,
+}
+
+impl EngineLibrary {
+    /// Returns the library loaded from `path`, loading it if no engine uses it yet.
+    pub(crate) fn load(path: &Path) -> Result<Arc<Self>, AppError> {
+        let mut loaded = LOADED_LIBRARIES.lock().unwrap();
+        loaded.retain(|library| library.strong_count() > 0);
+        if let Some(library) = loaded
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|library| library.path == path)
+        {
+            return Ok(library);
+        }
+
+        if !path.exists() {
+            error!("Engine not found at path: {}", path.display());
+            return Err(AppError::PathNoFound(path.to_path_buf()));
+        }
+        let library = unsafe { Library::new(path)? };
+        let get_proc_addresses = unsafe {
+            library.get::<fn(*mut FlutterEngineProcTable) -> FlutterEngineResult>(
+                b"FlutterEngineGetProcAddresses\0",
+            )?
+        };
+        let mut procs = FlutterEngineProcTable {
+            struct_size: std::mem::size_of::<FlutterEngineProcTable>(),
+            ..Default::default()
+        };
+        let res = get_proc_addresses(&mut procs);
+        if res != FlutterEngineResult_kSuccess {
+            error!("Failed to get Flutter engine proc addresses: {:?}", res);
+            return Err(AppError::FlutterEngineError(res));
+        }
+        info!("Loaded Flutter engine from {}", path.display());
+
+        let library = Arc::new(Self {
+            path: path.to_path_buf(),
+            library,
+            procs,
+            running_engines: AtomicUsize::new(0),
+        });
+        loaded.push(Arc::downgrade(&library));
+        Ok(library)
+    }
+
+    pub(crate) fn procs(&self) -> FlutterEngineProcTable {
+        self.procs
+    }
+
+    /// Looks up an engine entry point that is exported but not part of the proc table.
+    pub(crate) fn symbol<T: Copy>(&self, name: &str) -> Result<T, AppError> {
+        let symbol = unsafe { self.library.get::<T>(name.as_bytes()) };
+        symbol.map(|symbol| *symbol).map_err(|error| {
+            error!("{} not found: {}", name, error);
+            AppError::FlutterEngineProcTable(name.to_string())
+        })
+    }
+
+    /// Identifies the library while it is loaded.
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    pub(crate) fn engine_started(&self) {
+        let running = self.running_engines.fetch_add(1, Ordering::SeqCst) + 1;
+        info!("{} Flutter engines running", running);
+        if running == 1 {
+            tracing_integration::attach_engine_timeline(self.id(), &self.procs);
+        }
+    }
+
+    pub(crate) fn engine_stopped(&self) {
+        let running = self.running_engines.fetch_sub(1, Ordering::SeqCst) - 1;
+        if running == 0 {
+            tracing_integration::detach_engine_timeline(self.id());
+            info!("Last Flutter engine shut down, the Dart VM is stopped");
+        } else {
+            info!("{} Flutter engines running", running);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Routing of the engine's log messages into `tracing`.
+//!
+//! Engine diagnostics such as `[ERROR:flutter/shell/common/shell.cc(120)] ...`
+//! become events with the target `flutter::<tag>`, the level of their severity
+//! and the source file and line. Everything else is output of the Dart program,
+//! e.g. from `print`, and is logged at info level with the target `dart::<tag>`.
+//! Hosts that build their own subscriber need the `tracing-log` feature of
+//! `tracing-subscriber` to see these targets instead of `log`.
+
+use std::fmt;
+use std::sync::Arc;
+
+use log::{Level, Record};
+
+/// Receives the Dart output of an engine, with the log tag and the message,
+/// on the engine's UI thread.
+#[derive(Clone)]
+pub struct DartLogCallback(Arc<DartLogFn>);
+
+type DartLogFn = dyn Fn(&str, &str) + Send + Sync;
+
+impl DartLogCallback {
+    pub fn new(callback: impl Fn(&str, &str) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for DartLogCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DartLogCallback")
+    }
+}
+
+/// A diagnostic logged by the engine's C++ code.
+#[derive(Debug)]
+struct Diagnostic<'a> {
+    level: Level,
+    file: &'a str,
+    line: Option<u32>,
+    message: &'a str,
+}
+
+/// Parses the `[SEVERITY:file(line)] message` format of the engine's logging.
+fn parse_diagnostic(message: &str) -> Option<Diagnostic<'_>> {
+    let (header, message) = message.strip_prefix('[')?.split_once(']')?;
+    let (severity, location) = header.split_once(':')?;
+    let level = match severity {
+        "FATAL" | "ERROR" => Level::Error,
+        "WARNING" => Level::Warn,
+        "INFO" | "IMPORTANT" => Level::Info,
+        verbose if verbose.starts_with("VERBOSE") => Level::Debug,
+        _ => return None,
+    };
+    let (file, line) = match location
+        .strip_suffix(')')
+        .and_then(|location| location.rsplit_once('('))
+    {
+        Some((file, line)) => (file, line.parse().ok()),
+        None => (location, None),
+    };
+    Some(Diagnostic {
+        level,
+        file,
+        line,
+        message: message.trim_start(),
+    })
+}
+
+/// Logs a message the engine reported with `tag`, and passes Dart output on
+/// to `on_dart_log`.
+pub(crate) fn route(tag: &str, message: &str, on_dart_log: Option<&DartLogCallback>) {
+    // `format_trace` dispatches to the current subscriber and never fails.
+    match parse_diagnostic(message) {
+        Some(diagnostic) => {
+            let _ = tracing_log::format_trace(
+                &Record::builder()
+                    .level(diagnostic.level)
+                    .target(&format!("flutter::{}", tag))
+                    .file(Some(diagnostic.file))
+                    .line(diagnostic.line)
+                    .args(format_args!("{}", diagnostic.message))
+                    .build(),
+            );
+        }
+        None => {
+            let _ = tracing_log::format_trace(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target(&format!("dart::{}", tag))
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+            if let Some(on_dart_log) = on_dart_log {
+                (on_dart_log.0)(tag, message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_engine_diagnostics() {
+        let diagnostic =
+            parse_diagnostic("[ERROR:flutter/shell/common/shell.cc(120)] Engine run failed")
+                .unwrap();
+        assert_eq!(diagnostic.level, Level::Error);
+        assert_eq!(diagnostic.file, "flutter/shell/common/shell.cc");
+        assert_eq!(diagnostic.line, Some(120));
+        assert_eq!(diagnostic.message, "Engine run failed");
+
+        let diagnostic =
+            parse_diagnostic("[VERBOSE-2:dart_isolate.cc(147)] Running root isolate").unwrap();
+        assert_eq!(diagnostic.level, Level::Debug);
+        assert_eq!(diagnostic.file, "dart_isolate.cc");
+        assert_eq!(diagnostic.line, Some(147));
+        assert_eq!(diagnostic.message, "Running root isolate");
+
+        assert_eq!(
+            parse_diagnostic("[WARNING:a.cc(1)] x").unwrap().level,
+            Level::Warn
+        );
+        assert_eq!(
+            parse_diagnostic("[IMPORTANT:a.cc(1)] x").unwrap().level,
+            Level::Info
+        );
+    }
+
+    #[test]
+    fn parses_diagnostics_without_a_line() {
+        let diagnostic = parse_diagnostic("[INFO:flutter/runtime/dart_vm.cc] Using VM").unwrap();
+        assert_eq!(diagnostic.level, Level::Info);
+        assert_eq!(diagnostic.file, "flutter/runtime/dart_vm.cc");
+        assert_eq!(diagnostic.line, None);
+        assert_eq!(diagnostic.message, "Using VM");
+
+        let diagnostic = parse_diagnostic("[FATAL:main.cc(x)] Check failed").unwrap();
+        assert_eq!(diagnostic.file, "main.cc");
+        assert_eq!(diagnostic.line, None);
+    }
+
+    #[test]
+    fn leaves_dart_output_alone() {
+        for message in [
+            "[1, 2, 3]",
+            "[INFO] starting",
+            "[note:todo] later",
+            "[ERROR:unterminated",
+            "Hello [ERROR:a.cc(1)] world",
+            "",
+        ] {
+            assert!(
+                parse_diagnostic(message).is_none(),
+                "{:?} parsed as a diagnostic",
+                message
+            );
+        }
+    }
+}
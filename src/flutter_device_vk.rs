@@ -0,0 +1,319 @@
+//! Picking and opening a Vulkan device the Flutter engine can render with.
+
+use std::ffi::CStr;
+
+use ash::vk;
+use tracing::{debug, error};
+
+use crate::application::{AppError, GPUContextOptions};
+
+/// Device extensions the engine requires of the device it is given.
+const REQUIRED_DEVICE_EXTENSIONS: &[&CStr] = &[ash::khr::swapchain::NAME];
+
+/// Device extensions the engine uses when they are enabled.
+const OPTIONAL_DEVICE_EXTENSIONS: &[&CStr] = &[ash::ext::pipeline_creation_feedback::NAME];
+
+/// The oldest Vulkan version the engine renders with.
+const MIN_API_VERSION: u32 = vk::API_VERSION_1_1;
+
+/// The color format the engine renders into, as a blended attachment that is
+/// then sampled.
+const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const COLOR_FORMAT_FEATURES: vk::FormatFeatureFlags = vk::FormatFeatureFlags::from_raw(
+    vk::FormatFeatureFlags::COLOR_ATTACHMENT.as_raw()
+        | vk::FormatFeatureFlags::COLOR_ATTACHMENT_BLEND.as_raw()
+        | vk::FormatFeatureFlags::SAMPLED_IMAGE.as_raw(),
+);
+
+/// The engine needs one of these for its depth and stencil attachments.
+const DEPTH_STENCIL_FORMATS: &[vk::Format] = &[
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+];
+
+/// Features the compositor uses when the adapter has them, for the GPU times
+/// of [`FrameStats`](crate::frame_stats::FrameStats).
+pub(crate) const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::TIMESTAMP_QUERY;
+
+/// Orders adapters by `power_preference`, lowest first. Software adapters
+/// such as lavapipe come last.
+pub(crate) fn adapter_rank(
+    info: &wgpu::AdapterInfo,
+    power_preference: wgpu::PowerPreference,
+) -> u8 {
+    match (info.device_type, power_preference) {
+        (wgpu::DeviceType::IntegratedGpu, wgpu::PowerPreference::LowPower) => 0,
+        (wgpu::DeviceType::DiscreteGpu, wgpu::PowerPreference::LowPower) => 1,
+        (wgpu::DeviceType::DiscreteGpu, _) => 0,
+        (wgpu::DeviceType::IntegratedGpu, _) => 1,
+        (wgpu::DeviceType::VirtualGpu, _) => 2,
+        (wgpu::DeviceType::Other, _) => 3,
+        (wgpu::DeviceType::Cpu, _) => 4,
+    }
+}
+
+/// What `adapter` lacks to run Flutter with `options`, empty if nothing.
+pub(crate) fn missing_requirements(
+    adapter: &wgpu::Adapter,
+    options: &GPUContextOptions,
+) -> Vec<String> {
+    let info = adapter.get_info();
+    if info.backend != wgpu::Backend::Vulkan {
+        return vec![format!("Vulkan, it uses {}", info.backend)];
+    }
+    let mut missing = Vec::new();
+    if info.device_type == wgpu::DeviceType::Cpu && !options.allow_software {
+        missing.push("hardware rendering, software adapters are not allowed".to_owned());
+    }
+    let features = options.required_features - adapter.features();
+    if !features.is_empty() {
+        missing.push(format!("the features {:?}", features));
+    }
+    options.required_limits.check_limits_with_fail_fn(
+        &adapter.limits(),
+        false,
+        |name, requested, allowed| {
+            missing.push(format!(
+                "the limit {} of {}, it allows {}",
+                name, requested, allowed
+            ));
+        },
+    );
+    let vulkan = unsafe {
+        adapter.as_hal::<wgpu_hal::api::Vulkan, _, _>(|adapter| {
+            adapter.map(|adapter| {
+                let capabilities = adapter.physical_device_capabilities();
+                let mut missing =
+                    unmet_minimum(adapter.shared_instance(), adapter.raw_physical_device());
+                missing.extend(
+                    REQUIRED_DEVICE_EXTENSIONS
+                        .iter()
+                        .filter(|&&extension| !capabilities.supports_extension(extension))
+                        .map(|extension| format!("the device extension {:?}", extension)),
+                );
+                missing
+            })
+        })
+    };
+    missing.extend(vulkan.unwrap_or_default());
+    missing
+}
+
+/// The API version the engine is told the instance was created with, which
+/// is what wgpu sets in `VkApplicationInfo::apiVersion`.
+pub(crate) fn instance_api_version(instance: &wgpu_hal::vulkan::InstanceShared) -> u32 {
+    if instance.instance_api_version() < vk::API_VERSION_1_1 {
+        vk::API_VERSION_1_0
+    } else {
+        vk::API_VERSION_1_3
+    }
+}
+
+fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version)
+    )
+}
+
+/// What `physical_device` and `instance` lack of the engine's minimum: Vulkan
+/// 1.1, 4x multisampled color attachments, and the formats it renders into.
+fn unmet_minimum(
+    instance: &wgpu_hal::vulkan::InstanceShared,
+    physical_device: vk::PhysicalDevice,
+) -> Vec<String> {
+    let raw_instance = instance.raw_instance();
+    let properties = unsafe { raw_instance.get_physical_device_properties(physical_device) };
+    let format_features = |format| unsafe {
+        raw_instance
+            .get_physical_device_format_properties(physical_device, format)
+            .optimal_tiling_features
+    };
+
+    let mut missing = Vec::new();
+    if instance.instance_api_version() < MIN_API_VERSION {
+        missing.push(format!(
+            "Vulkan {}, the instance supports {}",
+            format_version(MIN_API_VERSION),
+            format_version(instance.instance_api_version())
+        ));
+    }
+    if properties.api_version < MIN_API_VERSION {
+        missing.push(format!(
+            "Vulkan {}, the driver supports {}",
+            format_version(MIN_API_VERSION),
+            format_version(properties.api_version)
+        ));
+    }
+    let sample_counts = vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4;
+    if !properties
+        .limits
+        .framebuffer_color_sample_counts
+        .contains(sample_counts)
+    {
+        missing.push(format!(
+            "4x multisampled color attachments, it supports {:?}",
+            properties.limits.framebuffer_color_sample_counts
+        ));
+    }
+    if !format_features(COLOR_FORMAT).contains(COLOR_FORMAT_FEATURES) {
+        missing.push(format!(
+            "{:?} with {:?}",
+            COLOR_FORMAT, COLOR_FORMAT_FEATURES
+        ));
+    }
+    if !DEPTH_STENCIL_FORMATS.iter().any(|&format| {
+        format_features(format).contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    }) {
+        missing.push(format!(
+            "a depth stencil attachment format of {:?}",
+            DEPTH_STENCIL_FORMATS
+        ));
+    }
+    missing
+}
+
+/// The name of the physical device of `device` and what it lacks of the
+/// engine's minimum, or `None` if it is not a Vulkan device.
+pub(crate) fn unmet_device_minimum(device: &wgpu::Device) -> Option<(String, Vec<String>)> {
+    unsafe {
+        device.as_hal::<wgpu_hal::api::Vulkan, _, _>(|device| {
+            device.map(|device| {
+                let instance = device.shared_instance();
+                let physical_device = device.raw_physical_device();
+                let properties = instance
+                    .raw_instance()
+                    .get_physical_device_properties(physical_device);
+                let name = properties.device_name_as_c_str().map_or_else(
+                    |_| "The GPU".to_owned(),
+                    |name| name.to_string_lossy().into_owned(),
+                );
+                (name, unmet_minimum(instance, physical_device))
+            })
+        })
+    }
+}
+
+/// The extensions Flutter requires that `device` was created without, or
+/// `None` if it is not a Vulkan device.
+pub(crate) fn missing_device_extensions(device: &wgpu::Device) -> Option<Vec<String>> {
+    unsafe {
+        device.as_hal::<wgpu_hal::api::Vulkan, _, _>(|device| {
+            device.map(|device| {
+                let enabled = device.enabled_device_extensions();
+                REQUIRED_DEVICE_EXTENSIONS
+                    .iter()
+                    .filter(|extension| !enabled.contains(extension))
+                    .map(|extension| format!("the device extension {:?}", extension))
+                    .collect()
+            })
+        })
+    }
+}
+
+/// Opens a device on `adapter` with the extensions wgpu needs for `features`
+/// and those the engine needs or makes use of. Its uncaptured GPU errors are
+/// logged.
+pub(crate) fn open_device(
+    adapter: &wgpu::Adapter,
+    features: wgpu::Features,
+    limits: wgpu::Limits,
+) -> Result<(wgpu::Device, wgpu::Queue), AppError> {
+    let memory_hints = wgpu::MemoryHints::default();
+    let open_device = unsafe {
+        adapter.as_hal::<wgpu_hal::api::Vulkan, _, _>(|hal_adapter| match hal_adapter {
+            Some(hal_adapter) => open_hal_device(hal_adapter, features, &memory_hints),
+            None => {
+                let backend = adapter.get_info().backend;
+                error!("The adapter uses {}, Flutter needs Vulkan", backend);
+                Err(AppError::UnsupportedBackend(backend))
+            }
+        })
+    }?;
+    let descriptor = wgpu::DeviceDescriptor {
+        label: Some("flutter"),
+        required_features: features,
+        required_limits: limits,
+        memory_hints,
+    };
+    let (device, queue) = unsafe { adapter.create_device_from_hal(open_device, &descriptor, None) }
+        .map_err(|error| {
+            error!("Failed to create a GPU device: {}", error);
+            AppError::RequestDevice(error)
+        })?;
+    // Logged rather than panicking, since every view's work fails once the
+    // device is lost, until the views move to a new device.
+    device.on_uncaptured_error(Box::new(|error| {
+        error!("Uncaptured GPU error: {}", error);
+    }));
+    Ok((device, queue))
+}
+
+/// Creates the device like wgpu does, with more extensions enabled.
+///
+/// # Safety
+///
+/// `adapter` must be the Vulkan adapter of the wgpu adapter the device is
+/// then created from.
+unsafe fn open_hal_device(
+    adapter: &wgpu_hal::vulkan::Adapter,
+    features: wgpu::Features,
+    memory_hints: &wgpu::MemoryHints,
+) -> Result<wgpu_hal::OpenDevice<wgpu_hal::api::Vulkan>, AppError> {
+    let capabilities = adapter.physical_device_capabilities();
+    let mut extensions = adapter.required_device_extensions(features);
+    for &extension in REQUIRED_DEVICE_EXTENSIONS
+        .iter()
+        .chain(OPTIONAL_DEVICE_EXTENSIONS)
+    {
+        if capabilities.supports_extension(extension) && !extensions.contains(&extension) {
+            extensions.push(extension);
+        }
+    }
+    debug!("device extensions {:?}", extensions);
+    let mut device_features = adapter.physical_device_features(&extensions, features);
+
+    // wgpu submits to the first queue of family 0.
+    let queue_family_index = 0;
+    let queue_infos = [vk::DeviceQueueCreateInfo::default()
+        .queue_family_index(queue_family_index)
+        .queue_priorities(&[1.0])];
+    let extension_names = extensions
+        .iter()
+        .map(|extension| extension.as_ptr())
+        .collect::<Vec<_>>();
+    let info = device_features.add_to_device_create(
+        vk::DeviceCreateInfo::default()
+            .queue_create_infos(&queue_infos)
+            .enabled_extension_names(&extension_names),
+    );
+    let raw_device = unsafe {
+        adapter.shared_instance().raw_instance().create_device(
+            adapter.raw_physical_device(),
+            &info,
+            None,
+        )
+    }
+    .map_err(|result| {
+        error!("Failed to create the Vulkan device: {}", result);
+        AppError::CreateVulkanDevice(result)
+    })?;
+
+    unsafe {
+        adapter.device_from_raw(
+            raw_device,
+            None,
+            &extensions,
+            features,
+            memory_hints,
+            queue_family_index,
+            0,
+        )
+    }
+    .map_err(|error| {
+        error!("Failed to open the Vulkan device: {}", error);
+        AppError::OpenDevice(error)
+    })
+}
@@ -5,6 +5,42 @@
 
 include!(concat!(env!("OUT_DIR"), "/flutter_embedder_bindings.rs"));
 
+// `FlutterEngineAddView` and its types are missing from the bundled header, and
+// neither it nor `FlutterEngineRemoveView` is part of the proc table. The
+// declarations below mirror the engine's, and both entry points are looked up
+// in the engine library by name.
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct FlutterAddViewResult {
+    pub struct_size: usize,
+    pub added: bool,
+    pub user_data: *mut ::core::ffi::c_void,
+}
+
+pub type FlutterAddViewCallback =
+    ::core::option::Option<unsafe extern "C" fn(result: *const FlutterAddViewResult)>;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct FlutterAddViewInfo {
+    pub struct_size: usize,
+    pub view_id: FlutterViewId,
+    pub view_metrics: *const FlutterWindowMetricsEvent,
+    pub user_data: *mut ::core::ffi::c_void,
+    pub add_view_callback: FlutterAddViewCallback,
+}
+
+pub type FlutterEngineAddViewFnPtr = unsafe extern "C" fn(
+    engine: FlutterEngine,
+    info: *const FlutterAddViewInfo,
+) -> FlutterEngineResult;
+
+pub type FlutterEngineRemoveViewFnPtr = unsafe extern "C" fn(
+    engine: FlutterEngine,
+    info: *const FlutterRemoveViewInfo,
+) -> FlutterEngineResult;
+
 use libloading::{Library, Symbol};
 use std::path::Path;
 use tracing::error;
@@ -1,10 +1,11 @@
 use std::ffi::CString;
 
 use ash::vk::Handle;
-use tracing::{debug, instrument};
+use tracing::{debug, error, instrument};
 
 use crate::{
-    application::get_instance_proc_address_callback,
+    application::{get_instance_proc_address_callback, AppError},
+    flutter_device_vk,
     flutter_embedder::{
         FlutterFrameInfo, FlutterRendererConfig, FlutterRendererType_kVulkan, FlutterVulkanImage,
         FlutterVulkanInstanceHandle, FlutterVulkanRendererConfig,
@@ -43,7 +44,7 @@ fn extract_raw_vk_instance(instance: &wgpu::Instance) -> Option<RawVkInstance> {
 
                 RawVkInstance {
                     instance: raw_void,
-                    version: 0,
+                    version: flutter_device_vk::instance_api_version(instance.shared_instance()),
                     extensions: extensions,
                 }
             })
@@ -80,12 +81,31 @@ pub struct FlutterRendererConfigWrapper {
     _owned_device_extensions: Vec<*const std::ffi::c_char>,
 }
 
+/// Fails with [`AppError::UnsupportedBackend`] when the instance or device is
+/// not a Vulkan one, which sessions rule out earlier with a clearer backend,
+/// and with [`AppError::MissingVulkanSupport`] when the device is below the
+/// engine's minimum, which the engine would fail on less clearly.
 pub fn create_flutter_renderer_config(
     instance: &wgpu::Instance,
     device: &wgpu::Device,
-) -> FlutterRendererConfigWrapper {
-    let raw_instance = extract_raw_vk_instance(&instance).unwrap();
-    let raw_device = extract_raw_vk_device(&device).unwrap();
+) -> Result<FlutterRendererConfigWrapper, AppError> {
+    let (Some(raw_instance), Some(raw_device)) = (
+        extract_raw_vk_instance(&instance),
+        extract_raw_vk_device(&device),
+    ) else {
+        error!("The wgpu instance or device is not backed by Vulkan");
+        return Err(AppError::UnsupportedBackend(wgpu::Backend::Empty));
+    };
+    if let Some((adapter, missing)) = flutter_device_vk::unmet_device_minimum(device) {
+        if !missing.is_empty() {
+            error!(
+                "{} cannot run Flutter, it lacks {}",
+                adapter,
+                missing.join(", ")
+            );
+            return Err(AppError::MissingVulkanSupport { adapter, missing });
+        }
+    }
 
     debug!("instance extensions {:?}", raw_instance.extensions);
     debug!("device extensions {:?}", raw_device.extensions);
@@ -118,11 +138,11 @@ pub fn create_flutter_renderer_config(
     vk.get_next_image_callback = Some(get_next_image_callback);
     vk.present_image_callback = Some(present_image_callback);
 
-    FlutterRendererConfigWrapper {
+    Ok(FlutterRendererConfigWrapper {
         config: config,
         _owned_instance_extensions: enabled_instance_extensions,
         _owned_device_extensions: enabled_device_extensions,
-    }
+    })
 }
 
 extern "C" fn present_image_callback(
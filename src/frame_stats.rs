@@ -0,0 +1,468 @@
+//! Per-frame timing of the embedder, so performance regressions show up in
+//! numbers rather than in user reports.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, error};
+use wgpu::util::DeviceExt;
+
+use crate::flutter_embedder::FlutterViewId;
+
+/// The timing of a frame presented to a view.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    pub view_id: FlutterViewId,
+    /// From the vsync the engine started the frame at to the frame being
+    /// presented. `None` before the first vsync.
+    pub vsync_to_present: Option<Duration>,
+    /// Time spent in the engine's present callback, compositing the layers.
+    pub present_duration: Duration,
+    /// GPU time of the composition pass of the latest frame the GPU finished,
+    /// usually an earlier one. `None` unless the device was created with
+    /// `wgpu::Features::TIMESTAMP_QUERY`.
+    pub gpu_duration: Option<Duration>,
+    /// Backing stores the engine had created since the previous frame.
+    pub backing_stores_created: u32,
+    /// Backing stores the engine had collected since the previous frame.
+    pub backing_stores_collected: u32,
+    /// The number of vsync intervals the frame was presented after its target.
+    pub dropped_frames: u32,
+}
+
+/// Delivers the [`FrameStats`] of every view of an engine.
+#[derive(Clone, Debug, Default)]
+pub struct FrameStatsStream(Arc<Mutex<Vec<Sender<FrameStats>>>>);
+
+impl FrameStatsStream {
+    /// Receives the stats of the frames presented from now on, on the thread
+    /// that reads them. Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<FrameStats> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn publish(&self, stats: &FrameStats) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(stats.clone()).is_ok());
+    }
+}
+
+/// Collects the frame stats of an engine, whose vsync and backing stores are
+/// shared by all of its views.
+#[derive(Debug, Default)]
+pub(crate) struct FrameTimer {
+    stream: FrameStatsStream,
+    /// When the latest vsync was reported to the engine, and its interval.
+    vsync: Option<(Instant, Duration)>,
+    backing_stores_created: u32,
+    backing_stores_collected: u32,
+}
+
+impl FrameTimer {
+    pub(crate) fn stream(&self) -> FrameStatsStream {
+        self.stream.clone()
+    }
+
+    pub(crate) fn set_stream(&mut self, stream: FrameStatsStream) {
+        self.stream = stream;
+    }
+
+    /// The vsync interval, which is the frame budget.
+    pub(crate) fn interval(&self) -> Option<Duration> {
+        self.vsync.map(|(_, interval)| interval)
+    }
+
+    pub(crate) fn vsync(&mut self, interval: Duration) {
+        self.vsync = Some((Instant::now(), interval));
+    }
+
+    pub(crate) fn backing_store_created(&mut self) {
+        self.backing_stores_created += 1;
+    }
+
+    pub(crate) fn backing_store_collected(&mut self) {
+        self.backing_stores_collected += 1;
+    }
+
+    /// Publishes the stats of a frame of `view_id` that was presented now,
+    /// with a present callback that started at `present_started`.
+    pub(crate) fn presented(
+        &mut self,
+        view_id: FlutterViewId,
+        present_started: Instant,
+        gpu_duration: Option<Duration>,
+    ) -> FrameStats {
+        let now = Instant::now();
+        let vsync_to_present = self.vsync.map(|(vsync, _)| now - vsync);
+        let dropped_frames = self
+            .vsync
+            .map_or(0, |(vsync, interval)| dropped_frames(vsync, interval, now));
+        let stats = FrameStats {
+            view_id,
+            vsync_to_present,
+            present_duration: now - present_started,
+            gpu_duration,
+            backing_stores_created: std::mem::take(&mut self.backing_stores_created),
+            backing_stores_collected: std::mem::take(&mut self.backing_stores_collected),
+            dropped_frames,
+        };
+        if stats.dropped_frames > 0 {
+            debug!("View {} dropped {} frames", view_id, stats.dropped_frames);
+        }
+        self.stream.publish(&stats);
+        stats
+    }
+}
+
+/// The number of vsync intervals a frame targeting the vsync after `vsync` was
+/// presented late by, counting a partial interval as a whole one.
+fn dropped_frames(vsync: Instant, interval: Duration, presented: Instant) -> u32 {
+    let late = presented.saturating_duration_since(vsync + interval);
+    late.as_nanos().div_ceil(interval.as_nanos().max(1)) as u32
+}
+
+/// Measures the GPU time of a render pass with timestamp queries. The result
+/// is read back without blocking, once the GPU finished the pass.
+#[derive(Debug)]
+pub(crate) struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Set while the readback buffer is in use by a measurement.
+    pending: bool,
+    /// Whether mapping the readback buffer succeeded, once it finished.
+    mapped: Arc<Mutex<Option<bool>>>,
+    latest: Option<Duration>,
+}
+
+impl GpuTimer {
+    const SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+
+    /// `None` if the device cannot write timestamps.
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("frame timing"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame timing resolve"),
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame timing readback"),
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            pending: false,
+            mapped: Arc::new(Mutex::new(None)),
+            latest: None,
+        })
+    }
+
+    /// The GPU time of the latest measured pass that finished.
+    pub(crate) fn latest(&self) -> Option<Duration> {
+        self.latest
+    }
+
+    /// Reads back a finished measurement. Returns the timestamp writes for the
+    /// next pass, unless the previous measurement is still in flight.
+    pub(crate) fn begin(
+        &mut self,
+        device: &wgpu::Device,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        if self.pending {
+            device.poll(wgpu::Maintain::Poll);
+            match self.mapped.lock().unwrap().take() {
+                None => return None,
+                Some(true) => {
+                    let ticks = {
+                        let data = self.readback_buffer.slice(..).get_mapped_range();
+                        let start = u64::from_ne_bytes(data[0..8].try_into().unwrap());
+                        let end = u64::from_ne_bytes(data[8..16].try_into().unwrap());
+                        end.saturating_sub(start)
+                    };
+                    self.readback_buffer.unmap();
+                    self.latest = Some(Duration::from_nanos(
+                        (ticks as f64 * self.period as f64) as u64,
+                    ));
+                }
+                Some(false) => error!("Failed to read back the frame timestamps"),
+            }
+            self.pending = false;
+        }
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        })
+    }
+
+    /// Copies the timestamps of the measured pass out, after it was encoded.
+    pub(crate) fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            Self::SIZE,
+        );
+    }
+
+    /// Starts reading the timestamps back, after the pass was submitted.
+    pub(crate) fn submitted(&mut self) {
+        self.pending = true;
+        let mapped = self.mapped.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                *mapped.lock().unwrap() = Some(result.is_ok());
+            });
+    }
+}
+
+/// Frames shown in the overlay's graphs.
+const OVERLAY_FRAMES: usize = 120;
+const OVERLAY_BAR_WIDTH: f32 = 2.0;
+const OVERLAY_GRAPH_HEIGHT: f32 = 48.0;
+const OVERLAY_MARGIN: f32 = 8.0;
+/// The graphs span this many frame budgets.
+const OVERLAY_BUDGETS: f32 = 2.0;
+/// Floats per rect instance: the corners in clip space and the color.
+const OVERLAY_RECT_FLOATS: usize = 8;
+
+const OVERLAY_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const OVERLAY_BUDGET_LINE: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+const OVERLAY_ON_TIME: [f32; 4] = [0.3, 0.85, 0.3, 0.9];
+const OVERLAY_LATE: [f32; 4] = [0.95, 0.25, 0.2, 0.9];
+
+/// Graphs of recent frames drawn on top of a view, like Flutter's performance
+/// overlay: vsync to present latency above, present callback time below, with
+/// a line at the frame budget and bars over it in red.
+#[derive(Debug)]
+pub(crate) struct PerformanceOverlay {
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    /// Latency and present time of the recent frames, oldest first.
+    frames: VecDeque<(Duration, Duration)>,
+    budget: Duration,
+}
+
+impl PerformanceOverlay {
+    pub(crate) fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/overlay.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("performance overlay"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("performance overlay"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: (OVERLAY_RECT_FLOATS * std::mem::size_of::<f32>()) as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4],
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+        Self {
+            format,
+            pipeline,
+            frames: VecDeque::with_capacity(OVERLAY_FRAMES),
+            // Until a vsync tells the interval.
+            budget: Duration::from_micros(16_667),
+        }
+    }
+
+    pub(crate) fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub(crate) fn record(&mut self, stats: &FrameStats, budget: Option<Duration>) {
+        if self.frames.len() == OVERLAY_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((
+            stats.vsync_to_present.unwrap_or_default(),
+            stats.present_duration,
+        ));
+        if let Some(budget) = budget {
+            self.budget = budget;
+        }
+    }
+
+    /// Draws the graphs into the top left corner of a `target` sized pass.
+    pub(crate) fn draw(
+        &self,
+        device: &wgpu::Device,
+        renderpass: &mut wgpu::RenderPass,
+        target: (f32, f32),
+    ) {
+        let width = OVERLAY_FRAMES as f32 * OVERLAY_BAR_WIDTH;
+        let mut rects = Vec::new();
+        let graphs = [
+            self.frames.iter().map(|frame| frame.0).collect::<Vec<_>>(),
+            self.frames.iter().map(|frame| frame.1).collect::<Vec<_>>(),
+        ];
+        for (index, durations) in graphs.iter().enumerate() {
+            let top = OVERLAY_MARGIN + index as f32 * (OVERLAY_GRAPH_HEIGHT + OVERLAY_MARGIN);
+            let bottom = top + OVERLAY_GRAPH_HEIGHT;
+            rects.push((
+                [OVERLAY_MARGIN, top, OVERLAY_MARGIN + width, bottom],
+                OVERLAY_BACKGROUND,
+            ));
+            for (frame, duration) in durations.iter().enumerate() {
+                let budgets = duration.as_secs_f32() / self.budget.as_secs_f32();
+                let height = budgets.min(OVERLAY_BUDGETS) / OVERLAY_BUDGETS * OVERLAY_GRAPH_HEIGHT;
+                let left = OVERLAY_MARGIN + frame as f32 * OVERLAY_BAR_WIDTH;
+                let color = if budgets > 1.0 {
+                    OVERLAY_LATE
+                } else {
+                    OVERLAY_ON_TIME
+                };
+                rects.push((
+                    [left, bottom - height, left + OVERLAY_BAR_WIDTH, bottom],
+                    color,
+                ));
+            }
+            let budget_line = bottom - OVERLAY_GRAPH_HEIGHT / OVERLAY_BUDGETS;
+            rects.push((
+                [
+                    OVERLAY_MARGIN,
+                    budget_line,
+                    OVERLAY_MARGIN + width,
+                    budget_line + 1.0,
+                ],
+                OVERLAY_BUDGET_LINE,
+            ));
+        }
+
+        let mut bytes = Vec::with_capacity(rects.len() * OVERLAY_RECT_FLOATS * 4);
+        for ([left, top, right, bottom], color) in &rects {
+            let corners = [
+                left / target.0 * 2.0 - 1.0,
+                1.0 - top / target.1 * 2.0,
+                right / target.0 * 2.0 - 1.0,
+                1.0 - bottom / target.1 * 2.0,
+            ];
+            for value in corners.iter().chain(color) {
+                bytes.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        let instances = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("performance overlay"),
+            contents: &bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        renderpass.set_scissor_rect(0, 0, target.0 as u32, target.1 as u32);
+        renderpass.set_pipeline(&self.pipeline);
+        renderpass.set_vertex_buffer(0, instances.slice(..));
+        renderpass.draw(0..4, 0..rects.len() as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn frames_presented_in_time_drop_nothing() {
+        let vsync = Instant::now();
+        assert_eq!(dropped_frames(vsync, INTERVAL, vsync), 0);
+        assert_eq!(dropped_frames(vsync, INTERVAL, vsync + INTERVAL), 0);
+    }
+
+    #[test]
+    fn late_frames_round_up_to_whole_intervals() {
+        let vsync = Instant::now();
+        let deadline = vsync + INTERVAL;
+        assert_eq!(
+            dropped_frames(vsync, INTERVAL, deadline + Duration::from_nanos(1)),
+            1
+        );
+        assert_eq!(dropped_frames(vsync, INTERVAL, deadline + INTERVAL), 1);
+        assert_eq!(
+            dropped_frames(
+                vsync,
+                INTERVAL,
+                deadline + INTERVAL + Duration::from_nanos(1)
+            ),
+            2
+        );
+        assert_eq!(dropped_frames(vsync, Duration::ZERO, vsync), 0);
+    }
+
+    #[test]
+    fn presented_frames_take_the_backing_store_counts() {
+        let mut timer = FrameTimer::default();
+        let receiver = timer.stream().subscribe();
+        timer.backing_store_created();
+        timer.backing_store_created();
+        timer.backing_store_collected();
+        timer.vsync(Duration::from_secs(3600));
+
+        let stats = timer.presented(1, Instant::now(), None);
+        assert_eq!(stats.view_id, 1);
+        assert_eq!(stats.backing_stores_created, 2);
+        assert_eq!(stats.backing_stores_collected, 1);
+        assert_eq!(stats.dropped_frames, 0);
+        assert!(stats.vsync_to_present.is_some());
+        assert_eq!(receiver.try_recv().unwrap().backing_stores_created, 2);
+
+        let stats = timer.presented(1, Instant::now(), None);
+        assert_eq!(stats.backing_stores_created, 0);
+        assert_eq!(stats.backing_stores_collected, 0);
+    }
+
+    #[test]
+    fn frames_without_vsync_drop_nothing() {
+        let stats = FrameTimer::default().presented(1, Instant::now(), None);
+        assert_eq!(stats.vsync_to_present, None);
+        assert_eq!(stats.dropped_frames, 0);
+    }
+}
@@ -0,0 +1,133 @@
+use winit::dpi::PhysicalPosition;
+
+use crate::flutter_embedder::{
+    FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse, FlutterPointerEvent,
+    FlutterPointerMouseButtons_kFlutterPointerButtonMouseBack,
+    FlutterPointerMouseButtons_kFlutterPointerButtonMouseForward,
+    FlutterPointerMouseButtons_kFlutterPointerButtonMouseMiddle,
+    FlutterPointerMouseButtons_kFlutterPointerButtonMousePrimary,
+    FlutterPointerMouseButtons_kFlutterPointerButtonMouseSecondary, FlutterPointerPhase,
+    FlutterPointerPhase_kAdd, FlutterPointerPhase_kDown, FlutterPointerPhase_kHover,
+    FlutterPointerPhase_kMove, FlutterPointerPhase_kRemove, FlutterPointerPhase_kUp,
+};
+
+/// Mouse buttons that can be forwarded to Flutter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerButton {
+    Primary,
+    Secondary,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl PointerButton {
+    pub(crate) fn from_winit(button: winit::event::MouseButton) -> Option<Self> {
+        match button {
+            winit::event::MouseButton::Left => Some(Self::Primary),
+            winit::event::MouseButton::Right => Some(Self::Secondary),
+            winit::event::MouseButton::Middle => Some(Self::Middle),
+            winit::event::MouseButton::Back => Some(Self::Back),
+            winit::event::MouseButton::Forward => Some(Self::Forward),
+            winit::event::MouseButton::Other(_) => None,
+        }
+    }
+
+    fn flutter_button(self) -> i64 {
+        let button = match self {
+            Self::Primary => FlutterPointerMouseButtons_kFlutterPointerButtonMousePrimary,
+            Self::Secondary => FlutterPointerMouseButtons_kFlutterPointerButtonMouseSecondary,
+            Self::Middle => FlutterPointerMouseButtons_kFlutterPointerButtonMouseMiddle,
+            Self::Back => FlutterPointerMouseButtons_kFlutterPointerButtonMouseBack,
+            Self::Forward => FlutterPointerMouseButtons_kFlutterPointerButtonMouseForward,
+        };
+        button as i64
+    }
+}
+
+/// Tracks the mouse so that the engine receives phases in the order it expects:
+/// add, then hover or down/move/up, then remove.
+#[derive(Debug, Default)]
+pub(crate) struct PointerState {
+    added: bool,
+    buttons: i64,
+    position: PhysicalPosition<f64>,
+}
+
+impl PointerState {
+    /// The last position the pointer moved to.
+    pub(crate) fn position(&self) -> PhysicalPosition<f64> {
+        self.position
+    }
+
+    /// Events for the pointer moving to `position`, in physical pixels of the view.
+    pub(crate) fn moved(&mut self, position: PhysicalPosition<f64>) -> Vec<FlutterPointerEvent> {
+        self.position = position;
+        let mut events = self.add_if_needed();
+        let phase = if self.buttons == 0 {
+            FlutterPointerPhase_kHover
+        } else {
+            FlutterPointerPhase_kMove
+        };
+        events.push(self.event(phase));
+        events
+    }
+
+    /// Events for `button` being pressed or released at the last known position.
+    pub(crate) fn button(
+        &mut self,
+        button: PointerButton,
+        pressed: bool,
+    ) -> Vec<FlutterPointerEvent> {
+        let mut events = self.add_if_needed();
+        let was_down = self.buttons != 0;
+        if pressed {
+            self.buttons |= button.flutter_button();
+        } else {
+            self.buttons &= !button.flutter_button();
+        }
+        let phase = match (was_down, self.buttons != 0) {
+            (false, true) => FlutterPointerPhase_kDown,
+            (true, false) => FlutterPointerPhase_kUp,
+            (true, true) => FlutterPointerPhase_kMove,
+            (false, false) => return events,
+        };
+        events.push(self.event(phase));
+        events
+    }
+
+    /// Events for the pointer leaving the view.
+    pub(crate) fn left(&mut self) -> Vec<FlutterPointerEvent> {
+        if !self.added {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        if self.buttons != 0 {
+            self.buttons = 0;
+            events.push(self.event(FlutterPointerPhase_kUp));
+        }
+        self.added = false;
+        events.push(self.event(FlutterPointerPhase_kRemove));
+        events
+    }
+
+    fn add_if_needed(&mut self) -> Vec<FlutterPointerEvent> {
+        if self.added {
+            return Vec::new();
+        }
+        self.added = true;
+        vec![self.event(FlutterPointerPhase_kAdd)]
+    }
+
+    fn event(&self, phase: FlutterPointerPhase) -> FlutterPointerEvent {
+        FlutterPointerEvent {
+            struct_size: size_of::<FlutterPointerEvent>(),
+            phase,
+            x: self.position.x,
+            y: self.position.y,
+            device_kind: FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse,
+            buttons: self.buttons,
+            ..Default::default()
+        }
+    }
+}
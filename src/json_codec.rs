@@ -0,0 +1,483 @@
+//! Flutter's `JSONMessageCodec` and `JSONMethodCodec`, used by the system
+//! channels such as `flutter/platform`.
+
+use std::fmt;
+
+use crate::standard_codec::DecodeError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Looks up a key in an object value.
+    pub(crate) fn get(&self, key: &str) -> Option<&JsonValue> {
+        let Self::Object(entries) = self else {
+            return None;
+        };
+        entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn object<const N: usize>(entries: [(&str, JsonValue); N]) -> Self {
+        Self::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self, DecodeError> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(DecodeError::new("trailing characters after JSON value"));
+        }
+        Ok(value)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Number(value) if value.is_finite() => write!(f, "{}", value),
+            Self::Number(_) => f.write_str("null"),
+            Self::String(value) => write_string(f, value),
+            Self::Array(values) => {
+                f.write_str("[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            }
+            Self::Object(entries) => {
+                f.write_str("{")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// A method invocation on a `JSONMethodCodec` channel.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct JsonMethodCall {
+    pub(crate) method: String,
+    pub(crate) args: JsonValue,
+}
+
+impl JsonMethodCall {
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let text =
+            std::str::from_utf8(bytes).map_err(|_| DecodeError::new("JSON is not valid UTF-8"))?;
+        let call = JsonValue::parse(text)?;
+        let method = call
+            .get("method")
+            .and_then(JsonValue::as_str)
+            .ok_or(DecodeError::new("method name is not a string"))?;
+        Ok(Self {
+            method: method.to_string(),
+            args: call.get("args").cloned().unwrap_or(JsonValue::Null),
+        })
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        JsonValue::object([
+            ("method", self.method.as_str().into()),
+            ("args", self.args.clone()),
+        ])
+        .to_string()
+        .into_bytes()
+    }
+}
+
+pub(crate) fn encode_success_envelope(result: &JsonValue) -> Vec<u8> {
+    JsonValue::Array(vec![result.clone()])
+        .to_string()
+        .into_bytes()
+}
+
+/// Decodes a method call reply. Error envelopes are returned as their error code.
+pub(crate) fn decode_envelope(bytes: &[u8]) -> Result<Result<JsonValue, String>, DecodeError> {
+    let text =
+        std::str::from_utf8(bytes).map_err(|_| DecodeError::new("JSON is not valid UTF-8"))?;
+    match JsonValue::parse(text)? {
+        JsonValue::Array(mut values) if values.len() == 1 => Ok(Ok(values.remove(0))),
+        JsonValue::Array(values) if values.len() == 3 => {
+            Ok(Err(values[0].as_str().unwrap_or_default().to_string()))
+        }
+        _ => Err(DecodeError::new("reply is not an envelope")),
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|byte| byte.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(DecodeError::new("unexpected end of JSON"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn expect_literal(
+        &mut self,
+        literal: &str,
+        value: JsonValue,
+    ) -> Result<JsonValue, DecodeError> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(value)
+        } else {
+            Err(DecodeError::new("invalid JSON literal"))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, DecodeError> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'n') => self.expect_literal("null", JsonValue::Null),
+            Some(b't') => self.expect_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.expect_literal("false", JsonValue::Bool(false)),
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                loop {
+                    values.push(self.parse_value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        b',' => continue,
+                        b']' => return Ok(JsonValue::Array(values)),
+                        _ => return Err(DecodeError::new("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut entries = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(entries));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.parse_string()?;
+                    self.skip_whitespace();
+                    if self.next()? != b':' {
+                        return Err(DecodeError::new("expected ':'"));
+                    }
+                    entries.push((key, self.parse_value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        b',' => continue,
+                        b'}' => return Ok(JsonValue::Object(entries)),
+                        _ => return Err(DecodeError::new("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(_) => self.parse_number(),
+            None => Err(DecodeError::new("unexpected end of JSON")),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, DecodeError> {
+        let start = self.position;
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(JsonValue::Number)
+            .ok_or(DecodeError::new("invalid JSON number"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, DecodeError> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .ok_or(DecodeError::new("unexpected end of JSON"))?;
+        self.position += 4;
+        std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or(DecodeError::new("invalid unicode escape"))
+    }
+
+    fn parse_string(&mut self) -> Result<String, DecodeError> {
+        if self.next()? != b'"' {
+            return Err(DecodeError::new("expected a string"));
+        }
+        let mut bytes = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => match self.next()? {
+                    b'"' => bytes.push(b'"'),
+                    b'\\' => bytes.push(b'\\'),
+                    b'/' => bytes.push(b'/'),
+                    b'b' => bytes.push(0x08),
+                    b'f' => bytes.push(0x0c),
+                    b'n' => bytes.push(b'\n'),
+                    b'r' => bytes.push(b'\r'),
+                    b't' => bytes.push(b'\t'),
+                    b'u' => {
+                        let mut code = self.parse_hex4()?;
+                        if (0xd800..0xdc00).contains(&code)
+                            && self.bytes[self.position..].starts_with(b"\\u")
+                        {
+                            self.position += 2;
+                            let low = self.parse_hex4()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(DecodeError::new("invalid surrogate pair"));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+                        bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                    _ => return Err(DecodeError::new("invalid escape")),
+                },
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| DecodeError::new("string is not valid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: &JsonValue) -> JsonValue {
+        JsonValue::parse(&value.to_string()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_values() {
+        let value = JsonValue::object([
+            ("null", JsonValue::Null),
+            (
+                "flags",
+                JsonValue::Array(vec![JsonValue::Bool(true), JsonValue::Bool(false)]),
+            ),
+            ("number", JsonValue::Number(-1.5)),
+            ("text", "caf\u{e9} \u{1f600}".into()),
+            ("empty", JsonValue::Object(Vec::new())),
+        ]);
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn encodes_compactly() {
+        let value = JsonValue::object([
+            (
+                "a",
+                JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Null]),
+            ),
+            ("b", "c".into()),
+        ]);
+        assert_eq!(value.to_string(), r#"{"a":[1,null],"b":"c"}"#);
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let value = JsonValue::from("\"\\\n\r\t\u{1}/");
+        assert_eq!(value.to_string(), r#""\"\\\n\r\t\u0001/""#);
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn parses_escapes() {
+        assert_eq!(
+            JsonValue::parse(r#""\"\\\/\b\f\n\r\t\u00e9""#).unwrap(),
+            JsonValue::from("\"\\/\u{8}\u{c}\n\r\t\u{e9}")
+        );
+    }
+
+    #[test]
+    fn parses_surrogate_pairs() {
+        assert_eq!(
+            JsonValue::parse(r#""\ud83d\ude00""#).unwrap(),
+            JsonValue::from("\u{1f600}")
+        );
+        assert_eq!(
+            JsonValue::parse(r#""\ud83d""#).unwrap(),
+            JsonValue::from("\u{fffd}")
+        );
+        assert!(JsonValue::parse(r#""\ud83d\u0041""#).is_err());
+    }
+
+    #[test]
+    fn parses_numbers() {
+        for (text, number) in [
+            ("0", 0.0),
+            ("-12", -12.0),
+            ("3.25", 3.25),
+            ("1e3", 1000.0),
+            ("-2.5E-2", -0.025),
+        ] {
+            assert_eq!(JsonValue::parse(text).unwrap(), JsonValue::Number(number));
+        }
+        assert_eq!(JsonValue::Number(f64::NAN).to_string(), "null");
+        assert_eq!(JsonValue::Number(0.1).to_string(), "0.1");
+    }
+
+    #[test]
+    fn parses_nested_values() {
+        let value = JsonValue::parse(r#" { "a" : [ 1 , { "b" : [ [ ] , { } ] } ] } "#).unwrap();
+        assert_eq!(
+            value,
+            JsonValue::object([(
+                "a",
+                JsonValue::Array(vec![
+                    JsonValue::Number(1.0),
+                    JsonValue::object([(
+                        "b",
+                        JsonValue::Array(vec![
+                            JsonValue::Array(Vec::new()),
+                            JsonValue::Object(Vec::new()),
+                        ]),
+                    )]),
+                ]),
+            )])
+        );
+        assert_eq!(value.get("a").and_then(|a| a.get("b")), None);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for text in [
+            "",
+            "{",
+            "[1,",
+            r#"{"a""#,
+            r#"{"a":"#,
+            r#""abc"#,
+            r#""\u12"#,
+            r#""\ud83d\u"#,
+            r#""\x""#,
+            "nul",
+            "tru",
+            "-",
+            "[1 2]",
+            r#"{"a" 1}"#,
+            r#"{1:2}"#,
+            "1 2",
+        ] {
+            assert!(JsonValue::parse(text).is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn decodes_method_calls() {
+        let call = JsonMethodCall {
+            method: "SystemChrome.setApplicationSwitcherDescription".to_string(),
+            args: JsonValue::object([("label", "app".into())]),
+        };
+        assert_eq!(JsonMethodCall::decode(&call.encode()).unwrap(), call);
+        assert!(JsonMethodCall::decode(br#"{"args":null}"#).is_err());
+        assert!(JsonMethodCall::decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn decodes_envelopes() {
+        let result = JsonValue::Bool(true);
+        assert_eq!(
+            decode_envelope(&encode_success_envelope(&result)).unwrap(),
+            Ok(result)
+        );
+        assert_eq!(
+            decode_envelope(br#"["error","message",null]"#).unwrap(),
+            Err("error".to_string())
+        );
+        assert!(decode_envelope(b"[]").is_err());
+        assert!(decode_envelope(b"[1,2").is_err());
+    }
+}
@@ -1,7 +1,27 @@
 pub mod application;
+mod asset_watcher;
+pub mod backing_store_pool;
 mod composition;
+mod displays;
+mod engine_library;
+pub mod engine_log;
+mod flutter_device_vk;
 mod flutter_embedder;
 mod flutter_render_config_vk;
-mod tracing_integration;
+pub mod frame_stats;
+pub mod input;
+mod json_codec;
+mod lifecycle;
+mod locales;
+pub mod platform_views;
+pub mod semantics;
+pub mod settings;
+pub mod standard_codec;
+pub mod surface;
+mod task_runner;
+pub mod texture_registry;
+pub mod texture_session;
+pub mod tracing_integration;
 mod utils;
+pub mod vm_service;
 mod windowing;
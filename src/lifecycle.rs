@@ -0,0 +1,45 @@
+use crate::json_codec::JsonValue;
+
+/// The channel the engine receives `AppLifecycleState` changes on, as strings.
+pub(crate) const LIFECYCLE_CHANNEL: &str = "flutter/lifecycle";
+
+/// The JSON method channel of `SystemNavigator` and `ServicesBinding.exitApplication`.
+pub(crate) const PLATFORM_CHANNEL: &str = "flutter/platform";
+
+/// Dart's `AppLifecycleState`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AppLifecycleState {
+    /// A window of the app has input focus.
+    Resumed,
+    /// A window is visible, but none has focus.
+    Inactive,
+    /// No window is visible, but not all of them are minimized.
+    Hidden,
+    /// Every window is minimized.
+    Paused,
+    /// The engine is about to shut down.
+    Detached,
+}
+
+impl AppLifecycleState {
+    pub(crate) fn message(self) -> &'static str {
+        match self {
+            Self::Resumed => "AppLifecycleState.resumed",
+            Self::Inactive => "AppLifecycleState.inactive",
+            Self::Hidden => "AppLifecycleState.hidden",
+            Self::Paused => "AppLifecycleState.paused",
+            Self::Detached => "AppLifecycleState.detached",
+        }
+    }
+}
+
+/// The reply Dart gives to `System.requestAppExit`, and the embedder gives
+/// to `System.exitApplication`.
+pub(crate) fn exit_response(exit: bool) -> JsonValue {
+    JsonValue::object([("response", if exit { "exit" } else { "cancel" }.into())])
+}
+
+/// Whether a `System.requestAppExit` reply allows the app to exit.
+pub(crate) fn allows_exit(reply: &JsonValue) -> bool {
+    reply.get("response").and_then(JsonValue::as_str) != Some("cancel")
+}
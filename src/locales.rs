@@ -0,0 +1,290 @@
+use std::ffi::{CStr, CString};
+use std::sync::Mutex;
+
+use tracing::{debug, warn};
+
+use crate::flutter_embedder::FlutterLocale;
+use crate::utils::catch_callback_panic;
+
+/// The locales most recently sent to an engine, see [`set_preferred_locales`].
+static PREFERRED_LOCALES: Mutex<Vec<Locale>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Locale {
+    language: String,
+    script: Option<String>,
+    country: Option<String>,
+    variant: Option<String>,
+}
+
+impl Locale {
+    /// Parses a BCP 47 tag like `zh-Hant-TW` or a POSIX locale like
+    /// `sr_RS.UTF-8@latin`. Returns `None` for the `C` and `POSIX` locales and
+    /// the undetermined language `und`.
+    pub(crate) fn parse(tag: &str) -> Option<Self> {
+        let (tag, modifier) = match tag.split_once('@') {
+            Some((tag, modifier)) => (tag, Some(modifier)),
+            None => (tag, None),
+        };
+        let tag = tag.split('.').next().unwrap_or_default();
+        let mut subtags = tag.split(['-', '_']);
+
+        let language = subtags.next()?;
+        if !(2..=3).contains(&language.len())
+            || !language.chars().all(|c| c.is_ascii_alphabetic())
+            || language.eq_ignore_ascii_case("und")
+        {
+            return None;
+        }
+        let mut locale = Self {
+            language: language.to_ascii_lowercase(),
+            ..Default::default()
+        };
+        for subtag in subtags {
+            let alphabetic = subtag.chars().all(|c| c.is_ascii_alphabetic());
+            let numeric = subtag.chars().all(|c| c.is_ascii_digit());
+            match subtag.len() {
+                // Extensions and private use subtags follow a singleton.
+                1 => break,
+                4 if alphabetic && locale.script.is_none() => {
+                    let (first, rest) = subtag.split_at(1);
+                    locale.script = Some(first.to_ascii_uppercase() + &rest.to_ascii_lowercase());
+                }
+                2 if alphabetic => locale.country = Some(subtag.to_ascii_uppercase()),
+                3 if numeric => locale.country = Some(subtag.to_string()),
+                4..=8 => locale.variant = Some(subtag.to_string()),
+                _ => debug!("Ignoring locale subtag {} of {}", subtag, tag),
+            }
+        }
+        match modifier {
+            Some("latin") => locale.script = Some("Latn".to_string()),
+            Some("cyrillic") => locale.script = Some("Cyrl".to_string()),
+            _ => {}
+        }
+        Some(locale)
+    }
+
+    fn from_flutter(locale: &FlutterLocale) -> Self {
+        let code = |code: *const ::core::ffi::c_char| {
+            (!code.is_null())
+                .then(|| {
+                    unsafe { CStr::from_ptr(code) }
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .filter(|code| !code.is_empty())
+        };
+        Self {
+            language: code(locale.language_code).unwrap_or_default(),
+            script: code(locale.script_code),
+            country: code(locale.country_code),
+            variant: code(locale.variant_code),
+        }
+    }
+}
+
+/// The user's locales, most preferred first, from `LANGUAGE` followed by the
+/// first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set, as gettext does.
+pub(crate) fn system_locales() -> Vec<Locale> {
+    let mut tags = Vec::new();
+    if let Ok(language) = std::env::var("LANGUAGE") {
+        tags.extend(language.split(':').map(str::to_string));
+    }
+    if let Some(tag) = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|tag| !tag.is_empty())
+    {
+        tags.push(tag);
+    }
+
+    let mut locales = Vec::new();
+    for locale in tags.iter().filter_map(|tag| Locale::parse(tag)) {
+        if !locales.contains(&locale) {
+            locales.push(locale);
+        }
+    }
+    locales
+}
+
+/// Parses locale tags given in the app configuration, dropping invalid ones.
+pub(crate) fn parse_locales(tags: &[String]) -> Vec<Locale> {
+    tags.iter()
+        .filter_map(|tag| {
+            let locale = Locale::parse(tag);
+            if locale.is_none() {
+                warn!("Ignoring invalid locale {}", tag);
+            }
+            locale
+        })
+        .collect()
+}
+
+/// Runs `f` with the locales converted to `FlutterLocale`s, which borrow
+/// strings that only live for the duration of the call.
+pub(crate) fn with_flutter_locales<R>(
+    locales: &[Locale],
+    f: impl FnOnce(&[*const FlutterLocale]) -> R,
+) -> R {
+    let c_string = |code: Option<&String>| code.and_then(|code| CString::new(code.as_str()).ok());
+    let strings = locales
+        .iter()
+        .map(|locale| {
+            [
+                c_string(Some(&locale.language)),
+                c_string(locale.country.as_ref()),
+                c_string(locale.script.as_ref()),
+                c_string(locale.variant.as_ref()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let as_ptr = |code: &Option<CString>| code.as_ref().map_or(std::ptr::null(), |c| c.as_ptr());
+    let flutter_locales = strings
+        .iter()
+        .map(|[language, country, script, variant]| FlutterLocale {
+            struct_size: std::mem::size_of::<FlutterLocale>(),
+            language_code: as_ptr(language),
+            country_code: as_ptr(country),
+            script_code: as_ptr(script),
+            variant_code: as_ptr(variant),
+        })
+        .collect::<Vec<_>>();
+    let pointers = flutter_locales
+        .iter()
+        .map(|locale| locale as *const FlutterLocale)
+        .collect::<Vec<_>>();
+    f(&pointers)
+}
+
+/// Makes `locales` the ones [`resolve_locale_callback`] resolves against. The
+/// callback gets no user data to find its engine, so with several engines the
+/// one that reported its locales last wins.
+pub(crate) fn set_preferred_locales(locales: &[Locale]) {
+    *PREFERRED_LOCALES.lock().unwrap() = locales.to_vec();
+}
+
+/// Picks the supported locale that best matches the preferred locales: the
+/// first preferred locale decides, matching language, script and country if
+/// possible and the language alone otherwise.
+fn resolve(preferred: &[Locale], supported: &[Locale]) -> Option<usize> {
+    let matches: [fn(&Locale, &Locale) -> bool; 4] = [
+        |a, b| a.language == b.language && a.script == b.script && a.country == b.country,
+        |a, b| a.language == b.language && a.country == b.country,
+        |a, b| a.language == b.language && a.script == b.script,
+        |a, b| a.language == b.language,
+    ];
+    preferred.iter().find_map(|preferred| {
+        matches.iter().find_map(|matches| {
+            supported
+                .iter()
+                .position(|supported| matches(preferred, supported))
+        })
+    })
+}
+
+/// Resolves the locale of the app from the locales its Dart code supports.
+/// Falls back to the first supported locale, as Flutter itself does.
+pub(crate) extern "C" fn resolve_locale_callback(
+    supported_locales: *mut *const FlutterLocale,
+    number_of_locales: usize,
+) -> *const FlutterLocale {
+    catch_callback_panic("resolve_locale_callback", std::ptr::null(), || {
+        if supported_locales.is_null() || number_of_locales == 0 {
+            return std::ptr::null();
+        }
+        let supported_locales =
+            unsafe { std::slice::from_raw_parts(supported_locales, number_of_locales) };
+        let supported = supported_locales
+            .iter()
+            .map(|&locale| {
+                unsafe { locale.as_ref() }
+                    .map(Locale::from_flutter)
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let preferred = PREFERRED_LOCALES.lock().unwrap();
+        let index = resolve(&preferred, &supported).unwrap_or(0);
+        debug!("Resolved locale {:?}", supported[index]);
+        supported_locales[index]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).unwrap()
+    }
+
+    fn locales(tags: &[&str]) -> Vec<Locale> {
+        tags.iter().map(|tag| locale(tag)).collect()
+    }
+
+    #[test]
+    fn parses_bcp47_tags() {
+        assert_eq!(
+            locale("zh-Hant-TW"),
+            Locale {
+                language: "zh".to_string(),
+                script: Some("Hant".to_string()),
+                country: Some("TW".to_string()),
+                variant: None,
+            }
+        );
+        assert_eq!(locale("ZH-hant-tw"), locale("zh-Hant-TW"));
+        assert_eq!(locale("es-419").country.as_deref(), Some("419"));
+        assert_eq!(locale("de-DE-1996").variant.as_deref(), Some("1996"));
+        assert_eq!(locale("en-US-x-private"), locale("en-US"));
+    }
+
+    #[test]
+    fn parses_posix_locales() {
+        assert_eq!(
+            locale("en_US"),
+            Locale {
+                language: "en".to_string(),
+                country: Some("US".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(locale("en_US.UTF-8"), locale("en_US"));
+        assert_eq!(locale("sr_RS.UTF-8@latin"), locale("sr-Latn-RS"));
+    }
+
+    #[test]
+    fn rejects_undetermined_locales() {
+        for tag in ["und", "und-US", "C", "C.UTF-8", "POSIX", "", "e1"] {
+            assert_eq!(Locale::parse(tag), None, "{:?} parsed", tag);
+        }
+    }
+
+    #[test]
+    fn resolves_in_fallback_order() {
+        let supported = locales(&[
+            "en",
+            "zh-Hans-CN",
+            "zh-TW",
+            "zh-Hant",
+            "zh-Hant-TW",
+            "fr-FR",
+        ]);
+        // Language, script and country.
+        assert_eq!(resolve(&locales(&["zh-Hant-TW"]), &supported), Some(4));
+        // Language and country.
+        assert_eq!(resolve(&locales(&["zh-Hans-TW"]), &supported), Some(2));
+        // Language and script.
+        assert_eq!(resolve(&locales(&["zh-Hant-HK"]), &supported), Some(3));
+        // Language alone, the first supported one.
+        assert_eq!(resolve(&locales(&["zh-Latn-HK"]), &supported), Some(1));
+    }
+
+    #[test]
+    fn resolves_by_the_first_matching_preference() {
+        let supported = locales(&["fr-FR", "de-AT"]);
+        assert_eq!(resolve(&locales(&["de-DE", "fr-FR"]), &supported), Some(1));
+        assert_eq!(resolve(&locales(&["ja", "fr-CA"]), &supported), Some(0));
+        assert_eq!(resolve(&locales(&["ja"]), &supported), None);
+        assert_eq!(resolve(&[], &supported), None);
+    }
+}
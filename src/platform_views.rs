@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tracing::{error, info, warn};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::composition::{Mat4, PixelRect};
+use crate::flutter_embedder::FlutterViewId;
+use crate::input::PointerButton;
+use crate::standard_codec::{
+    decode_message, encode_error_envelope, encode_success_envelope, EncodableValue, MethodCall,
+};
+
+/// The channel Dart uses to create and dispose platform views.
+pub(crate) const PLATFORM_VIEWS_CHANNEL: &str = "flutter/platform_views";
+
+/// Pointer input that hit a platform view. Positions are in physical pixels
+/// relative to the top left of the view's rendered texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlatformViewPointerEvent {
+    Moved(PhysicalPosition<f64>),
+    Button {
+        button: PointerButton,
+        pressed: bool,
+        position: PhysicalPosition<f64>,
+    },
+    Left,
+}
+
+/// Provides the native content of platform views of one view type.
+///
+/// Dart creates a view with `{"id": id, "viewType": type, "params": bytes}`
+/// on the `flutter/platform_views` channel, where `params` is optional and
+/// encoded with the standard message codec. The factory is then asked to render
+/// the view every frame it is part of, until Dart disposes it.
+///
+/// Methods are called with the registry locked, so they must not call back into it.
+pub trait PlatformViewFactory: Send {
+    /// Creates the view `id`. An `Err` is returned to Dart as a `PlatformException`.
+    fn create(&mut self, id: i64, params: &EncodableValue) -> Result<(), String>;
+
+    /// Renders the view at `size` physical pixels, the size it covers on screen.
+    /// The texture must come from `device` and have `TEXTURE_BINDING` usage;
+    /// it is stretched over the view if its size differs.
+    /// Returning `None` leaves the view out of the frame.
+    fn render(
+        &mut self,
+        id: i64,
+        size: PhysicalSize<u32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<wgpu::Texture>;
+
+    /// Pointer input over the view. Flutter receives the same input as well.
+    fn pointer_event(&mut self, _id: i64, _event: PlatformViewPointerEvent) {}
+
+    fn dispose(&mut self, id: i64);
+}
+
+/// Where a platform view was drawn in the last frame, used for hit-testing.
+#[derive(Clone, Debug)]
+pub(crate) struct PlatformViewPlacement {
+    pub(crate) id: i64,
+    /// Maps the unit quad onto the view in target pixels.
+    pub(crate) transform: Mat4,
+    pub(crate) size: PhysicalSize<u32>,
+    pub(crate) clip: Option<PixelRect>,
+}
+
+impl PlatformViewPlacement {
+    /// Maps a target position into the view's pixels. Only affine transforms are inverted.
+    fn to_local(&self, position: PhysicalPosition<f64>) -> Option<PhysicalPosition<f64>> {
+        let m = &self.transform;
+        let (a, b, c, d) = (
+            m[0][0] as f64,
+            m[1][0] as f64,
+            m[0][1] as f64,
+            m[1][1] as f64,
+        );
+        let det = a * d - b * c;
+        if det.abs() < f64::EPSILON {
+            return None;
+        }
+        let x = position.x - m[3][0] as f64;
+        let y = position.y - m[3][1] as f64;
+        let u = (d * x - b * y) / det;
+        let v = (a * y - c * x) / det;
+        Some(PhysicalPosition::new(
+            u * self.size.width as f64,
+            v * self.size.height as f64,
+        ))
+    }
+
+    fn contains(&self, position: PhysicalPosition<f64>) -> bool {
+        if let Some(clip) = &self.clip {
+            if !clip.contains(position.x as f32, position.y as f32) {
+                return false;
+            }
+        }
+        self.to_local(position).is_some_and(|local| {
+            (0.0..=self.size.width as f64).contains(&local.x)
+                && (0.0..=self.size.height as f64).contains(&local.y)
+        })
+    }
+}
+
+#[derive(Default)]
+struct RegistryState {
+    factories: HashMap<String, Box<dyn PlatformViewFactory>>,
+    /// The view type of every live view.
+    views: HashMap<i64, String>,
+    /// Placements of the last frame of each Flutter view, bottom to top.
+    placements: HashMap<FlutterViewId, Vec<PlatformViewPlacement>>,
+    pointer_view: Option<i64>,
+    pressed_buttons: u32,
+}
+
+impl RegistryState {
+    fn send_pointer_event(&mut self, id: i64, event: PlatformViewPointerEvent) {
+        let Some(factory) = self
+            .views
+            .get(&id)
+            .and_then(|view_type| self.factories.get_mut(view_type))
+        else {
+            return;
+        };
+        factory.pointer_event(id, event);
+    }
+
+    fn placement(&self, flutter_view_id: FlutterViewId, id: i64) -> Option<&PlatformViewPlacement> {
+        self.placements
+            .get(&flutter_view_id)?
+            .iter()
+            .rev()
+            .find(|placement| placement.id == id)
+    }
+
+    fn create(&mut self, arguments: &EncodableValue) -> Vec<u8> {
+        let (Some(id), Some(view_type)) = (
+            arguments.get("id").and_then(EncodableValue::as_i64),
+            arguments.get("viewType").and_then(EncodableValue::as_str),
+        ) else {
+            return encode_error_envelope(
+                "bad_arguments",
+                Some("create expects an id and a viewType"),
+            );
+        };
+        let params = match arguments.get("params") {
+            Some(EncodableValue::Uint8List(bytes)) => match decode_message(bytes) {
+                Ok(params) => params,
+                Err(error) => {
+                    return encode_error_envelope("bad_arguments", Some(&error.to_string()))
+                }
+            },
+            _ => EncodableValue::Null,
+        };
+        if self.views.contains_key(&id) {
+            return encode_error_envelope(
+                "recreating_view",
+                Some(&format!("view {} already exists", id)),
+            );
+        }
+        let Some(factory) = self.factories.get_mut(view_type) else {
+            warn!("No platform view factory registered for {}", view_type);
+            return encode_error_envelope(
+                "unregistered_view_type",
+                Some(&format!("no factory registered for {}", view_type)),
+            );
+        };
+        if let Err(message) = factory.create(id, &params) {
+            error!(
+                "Failed to create platform view {} of type {}: {}",
+                id, view_type, message
+            );
+            return encode_error_envelope("create_failed", Some(&message));
+        }
+        info!("Created platform view {} of type {}", id, view_type);
+        self.views.insert(id, view_type.to_string());
+        encode_success_envelope(&EncodableValue::Null)
+    }
+
+    fn dispose(&mut self, arguments: &EncodableValue) -> Vec<u8> {
+        let Some(id) = arguments
+            .as_i64()
+            .or_else(|| arguments.get("id").and_then(EncodableValue::as_i64))
+        else {
+            return encode_error_envelope("bad_arguments", Some("dispose expects an id"));
+        };
+        let Some(view_type) = self.views.remove(&id) else {
+            return encode_error_envelope(
+                "unknown_view",
+                Some(&format!("view {} does not exist", id)),
+            );
+        };
+        if let Some(factory) = self.factories.get_mut(&view_type) {
+            factory.dispose(id);
+        }
+        if self.pointer_view == Some(id) {
+            self.pointer_view = None;
+        }
+        info!("Disposed platform view {}", id);
+        encode_success_envelope(&EncodableValue::Null)
+    }
+}
+
+/// The platform view factories of an engine and the views Dart created with them.
+///
+/// Like [`crate::texture_registry::TextureRegistry`] it can be cloned and used
+/// from any thread, and factories can be registered before the engine starts.
+#[derive(Clone, Default)]
+pub struct PlatformViewRegistry {
+    state: Arc<Mutex<RegistryState>>,
+}
+
+impl fmt::Debug for PlatformViewRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("PlatformViewRegistry")
+            .field("factories", &state.factories.keys().collect::<Vec<_>>())
+            .field("views", &state.views)
+            .finish()
+    }
+}
+
+impl PlatformViewRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the factory for `view_type`, replacing any previous one.
+    pub fn register_factory(
+        &self,
+        view_type: impl Into<String>,
+        factory: impl PlatformViewFactory + 'static,
+    ) {
+        self.state
+            .lock()
+            .unwrap()
+            .factories
+            .insert(view_type.into(), Box::new(factory));
+    }
+
+    /// Ids of the views Dart has created and not disposed yet.
+    pub fn view_ids(&self) -> Vec<i64> {
+        self.state.lock().unwrap().views.keys().copied().collect()
+    }
+
+    /// Handles a method call on the `flutter/platform_views` channel and returns
+    /// the encoded reply, which is empty for methods that are not implemented.
+    pub(crate) fn handle_method_call(&self, call: &MethodCall) -> Vec<u8> {
+        let mut state = self.state.lock().unwrap();
+        match call.method.as_str() {
+            "create" => state.create(&call.arguments),
+            "dispose" => state.dispose(&call.arguments),
+            _ => Vec::new(),
+        }
+    }
+
+    pub(crate) fn render(
+        &self,
+        id: i64,
+        size: PhysicalSize<u32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<wgpu::Texture> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let factory = state.factories.get_mut(state.views.get(&id)?)?;
+        factory.render(id, size, device, queue)
+    }
+
+    pub(crate) fn set_placements(
+        &self,
+        flutter_view_id: FlutterViewId,
+        placements: Vec<PlatformViewPlacement>,
+    ) {
+        self.state
+            .lock()
+            .unwrap()
+            .placements
+            .insert(flutter_view_id, placements);
+    }
+
+    pub(crate) fn remove_placements(&self, flutter_view_id: FlutterViewId) {
+        self.state
+            .lock()
+            .unwrap()
+            .placements
+            .remove(&flutter_view_id);
+    }
+
+    /// Forwards a pointer move within a Flutter view to the topmost platform view
+    /// under `position`, or to the one that captured the pointer when a button
+    /// was pressed over it.
+    pub(crate) fn pointer_moved(
+        &self,
+        flutter_view_id: FlutterViewId,
+        position: PhysicalPosition<f64>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let captured = state.pointer_view.filter(|_| state.pressed_buttons > 0);
+        let hit = match captured {
+            Some(id) => state
+                .placement(flutter_view_id, id)
+                .and_then(|placement| placement.to_local(position))
+                .map(|local| (id, local)),
+            None => state
+                .placements
+                .get(&flutter_view_id)
+                .into_iter()
+                .flatten()
+                .rev()
+                .find(|placement| placement.contains(position))
+                .and_then(|placement| Some((placement.id, placement.to_local(position)?))),
+        };
+
+        let hit_id = hit.map(|(id, _)| id);
+        if let Some(previous) = state
+            .pointer_view
+            .filter(|&previous| Some(previous) != hit_id)
+        {
+            state.send_pointer_event(previous, PlatformViewPointerEvent::Left);
+            state.pressed_buttons = 0;
+        }
+        state.pointer_view = hit_id;
+        if let Some((id, local)) = hit {
+            state.send_pointer_event(id, PlatformViewPointerEvent::Moved(local));
+        }
+    }
+
+    pub(crate) fn pointer_button(
+        &self,
+        flutter_view_id: FlutterViewId,
+        button: PointerButton,
+        pressed: bool,
+        position: PhysicalPosition<f64>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let Some(id) = state.pointer_view else {
+            return;
+        };
+        let Some(local) = state
+            .placement(flutter_view_id, id)
+            .and_then(|placement| placement.to_local(position))
+        else {
+            return;
+        };
+        if pressed {
+            state.pressed_buttons += 1;
+        } else {
+            state.pressed_buttons = state.pressed_buttons.saturating_sub(1);
+        }
+        state.send_pointer_event(
+            id,
+            PlatformViewPointerEvent::Button {
+                button,
+                pressed,
+                position: local,
+            },
+        );
+    }
+
+    pub(crate) fn pointer_left(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pressed_buttons = 0;
+        if let Some(id) = state.pointer_view.take() {
+            state.send_pointer_event(id, PlatformViewPointerEvent::Left);
+        }
+    }
+
+    /// Disposes every live view, e.g. when the engine shuts down.
+    pub(crate) fn dispose_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        for (id, view_type) in state.views.drain() {
+            if let Some(factory) = state.factories.get_mut(&view_type) {
+                factory.dispose(id);
+            }
+        }
+        state.placements.clear();
+        state.pointer_view = None;
+        state.pressed_buttons = 0;
+    }
+}
@@ -0,0 +1,847 @@
+//! The semantics tree of an engine, which describes its UI to assistive
+//! technology and to automated tests.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::CStr;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use accesskit::{
+    Action, ActionData, ActionHandler, ActionRequest, ActivationHandler, Affine,
+    DeactivationHandler, Node, NodeId, Rect, Role, Toggled, Tree, TreeUpdate,
+};
+use tracing::{debug, error, warn};
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::application::AppError;
+use crate::composition::{flutter_transformation, mat4_mul, PixelRect, IDENTITY};
+use crate::flutter_embedder::*;
+use crate::standard_codec::{encode_message, EncodableValue};
+
+/// The id of the root of Flutter's semantics tree.
+const ROOT_NODE_ID: i32 = 0;
+
+/// A node of the semantics tree, copied out of a `FlutterSemanticsNode2`.
+#[derive(Clone, Debug)]
+pub struct SemanticsNode {
+    id: i32,
+    flags: FlutterSemanticsFlag,
+    actions: FlutterSemanticsAction,
+    label: String,
+    hint: String,
+    value: String,
+    tooltip: String,
+    /// The bounds of the node in its own coordinate system.
+    rect: FlutterRect,
+    /// Maps the node's coordinate system to its parent's.
+    transform: FlutterTransformation,
+    /// Child ids in traversal order.
+    children: Vec<i32>,
+}
+
+impl SemanticsNode {
+    fn from_flutter(node: &FlutterSemanticsNode2) -> Self {
+        let children = if node.children_in_traversal_order.is_null() {
+            Vec::new()
+        } else {
+            unsafe {
+                std::slice::from_raw_parts(node.children_in_traversal_order, node.child_count)
+            }
+            .to_vec()
+        };
+        Self {
+            id: node.id,
+            flags: node.flags,
+            actions: node.actions,
+            label: c_string(node.label),
+            hint: c_string(node.hint),
+            value: c_string(node.value),
+            tooltip: c_string(node.tooltip),
+            rect: node.rect,
+            transform: node.transform,
+            children,
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn hint(&self) -> &str {
+        &self.hint
+    }
+
+    pub fn tooltip(&self) -> &str {
+        &self.tooltip
+    }
+
+    /// Child ids in traversal order.
+    pub fn children(&self) -> &[i32] {
+        &self.children
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsFocused)
+    }
+
+    /// The actions the node supports, as reported to assistive technology.
+    pub fn actions(&self) -> Vec<Action> {
+        ACTIONS
+            .iter()
+            .filter(|(_, flutter_action)| self.has_action(*flutter_action))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    fn has_flag(&self, flag: FlutterSemanticsFlag) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn has_action(&self, action: FlutterSemanticsAction) -> bool {
+        self.actions & action != 0
+    }
+
+    /// The role assistive technology sees the node as.
+    pub fn role(&self) -> Role {
+        if self.id == ROOT_NODE_ID {
+            Role::Window
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsTextField) {
+            if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsObscured) {
+                Role::PasswordInput
+            } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsMultiline) {
+                Role::MultilineTextInput
+            } else {
+                Role::TextInput
+            }
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsSlider) {
+            Role::Slider
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagHasCheckedState) {
+            if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsInMutuallyExclusiveGroup) {
+                Role::RadioButton
+            } else {
+                Role::CheckBox
+            }
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagHasToggledState) {
+            Role::Switch
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsButton) {
+            Role::Button
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsLink) {
+            Role::Link
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsHeader) {
+            Role::Heading
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsImage) {
+            Role::Image
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagHasImplicitScrolling) {
+            Role::ScrollView
+        } else if self.children.is_empty() && !self.label.is_empty() {
+            Role::Label
+        } else {
+            Role::GenericContainer
+        }
+    }
+
+    fn to_accesskit(&self) -> Node {
+        let mut node = Node::new(self.role());
+        if !self.label.is_empty() {
+            node.set_label(self.label.as_str());
+        }
+        if !self.value.is_empty() {
+            node.set_value(self.value.as_str());
+        }
+        if !self.hint.is_empty() {
+            node.set_description(self.hint.as_str());
+        } else if !self.tooltip.is_empty() {
+            node.set_description(self.tooltip.as_str());
+        }
+        node.set_bounds(Rect::new(
+            self.rect.left,
+            self.rect.top,
+            self.rect.right,
+            self.rect.bottom,
+        ));
+        let t = &self.transform;
+        node.set_transform(Affine::new([
+            t.scaleX, t.skewY, t.skewX, t.scaleY, t.transX, t.transY,
+        ]));
+        node.set_children(
+            self.children
+                .iter()
+                .map(|&child| node_id(child))
+                .collect::<Vec<_>>(),
+        );
+
+        if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagHasCheckedState) {
+            node.set_toggled(
+                if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsCheckStateMixed) {
+                    Toggled::Mixed
+                } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsChecked) {
+                    Toggled::True
+                } else {
+                    Toggled::False
+                },
+            );
+        } else if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagHasToggledState) {
+            node.set_toggled(
+                if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsToggled) {
+                    Toggled::True
+                } else {
+                    Toggled::False
+                },
+            );
+        }
+        if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagHasEnabledState)
+            && !self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsEnabled)
+        {
+            node.set_disabled();
+        }
+        if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsSelected) {
+            node.set_selected(true);
+        }
+        if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagHasExpandedState) {
+            node.set_expanded(self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsExpanded));
+        }
+        if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsHidden) {
+            node.set_hidden();
+        }
+        if self.has_flag(FlutterSemanticsFlag_kFlutterSemanticsFlagIsReadOnly) {
+            node.set_read_only();
+        }
+
+        for (action, flutter_action) in ACTIONS {
+            if self.has_action(flutter_action) {
+                node.add_action(action);
+            }
+        }
+        node
+    }
+}
+
+/// AccessKit actions and the Flutter actions they are dispatched as. Flutter
+/// names scrolls after the swipe direction, so scrolling down to reveal the
+/// content below is Flutter's scroll up.
+const ACTIONS: [(Action, FlutterSemanticsAction); 9] = [
+    (
+        Action::Click,
+        FlutterSemanticsAction_kFlutterSemanticsActionTap,
+    ),
+    (
+        Action::ShowContextMenu,
+        FlutterSemanticsAction_kFlutterSemanticsActionLongPress,
+    ),
+    (
+        Action::ScrollDown,
+        FlutterSemanticsAction_kFlutterSemanticsActionScrollUp,
+    ),
+    (
+        Action::ScrollUp,
+        FlutterSemanticsAction_kFlutterSemanticsActionScrollDown,
+    ),
+    (
+        Action::ScrollRight,
+        FlutterSemanticsAction_kFlutterSemanticsActionScrollLeft,
+    ),
+    (
+        Action::ScrollLeft,
+        FlutterSemanticsAction_kFlutterSemanticsActionScrollRight,
+    ),
+    (
+        Action::Increment,
+        FlutterSemanticsAction_kFlutterSemanticsActionIncrease,
+    ),
+    (
+        Action::Decrement,
+        FlutterSemanticsAction_kFlutterSemanticsActionDecrease,
+    ),
+    (
+        Action::ScrollIntoView,
+        FlutterSemanticsAction_kFlutterSemanticsActionShowOnScreen,
+    ),
+];
+
+fn c_string(value: *const ::core::ffi::c_char) -> String {
+    if value.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(value) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+fn node_id(id: i32) -> NodeId {
+    NodeId(id as u64)
+}
+
+/// The semantics tree of the implicit view, kept up to date from the engine's
+/// incremental updates.
+#[derive(Debug, Default)]
+pub(crate) struct SemanticsTree {
+    nodes: HashMap<i32, SemanticsNode>,
+}
+
+impl SemanticsTree {
+    /// Applies an update and returns the ids of the nodes it changed. Nodes
+    /// that are no longer reachable from the root are dropped.
+    pub(crate) fn apply_update(&mut self, update: &FlutterSemanticsUpdate2) -> Vec<i32> {
+        let nodes = if update.nodes.is_null() {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(update.nodes, update.node_count) }
+        };
+        let mut changed = Vec::with_capacity(nodes.len());
+        for &node in nodes {
+            let Some(node) = (unsafe { node.as_ref() }) else {
+                continue;
+            };
+            let node = SemanticsNode::from_flutter(node);
+            changed.push(node.id);
+            self.nodes.insert(node.id, node);
+        }
+
+        let mut reachable = HashSet::new();
+        let mut pending = vec![ROOT_NODE_ID];
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.nodes.get(&id) {
+                if reachable.insert(id) {
+                    pending.extend(&node.children);
+                }
+            }
+        }
+        self.nodes.retain(|id, _| reachable.contains(id));
+        changed.retain(|id| reachable.contains(id));
+        changed
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    /// The nodes reachable from the root, in depth first traversal order.
+    fn traversal(&self) -> Vec<&SemanticsNode> {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        let mut pending = vec![ROOT_NODE_ID];
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.nodes.get(&id) {
+                nodes.push(node);
+                pending.extend(node.children.iter().rev());
+            }
+        }
+        nodes
+    }
+
+    /// The bounds of node `id` in physical pixels of the view.
+    fn rect_in_view(&self, id: i32) -> Option<Rect> {
+        let node = self.nodes.get(&id)?;
+        let mut transform = IDENTITY;
+        let mut current = Some(node);
+        while let Some(ancestor) = current {
+            transform = mat4_mul(&flutter_transformation(&ancestor.transform), &transform);
+            current = self
+                .nodes
+                .values()
+                .find(|parent| parent.children.contains(&ancestor.id));
+        }
+        let bounds = PixelRect::transformed_bounds(&transform, &node.rect);
+        Some(Rect::new(
+            bounds.left as f64,
+            bounds.top as f64,
+            bounds.right as f64,
+            bounds.bottom as f64,
+        ))
+    }
+
+    /// The AccessKit update for the nodes `ids`, or the whole tree when `ids` is `None`.
+    fn tree_update(&self, ids: Option<&[i32]>) -> Option<TreeUpdate> {
+        self.nodes.get(&ROOT_NODE_ID)?;
+        let nodes = match ids {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| self.nodes.get(id))
+                .map(|node| (node_id(node.id), node.to_accesskit()))
+                .collect(),
+            None => self
+                .nodes
+                .values()
+                .map(|node| (node_id(node.id), node.to_accesskit()))
+                .collect(),
+        };
+        let focus = self
+            .nodes
+            .values()
+            .find(|node| node.is_focused())
+            .map_or(ROOT_NODE_ID, |node| node.id);
+        Some(TreeUpdate {
+            nodes,
+            tree: Some(Tree::new(node_id(ROOT_NODE_ID))),
+            focus: node_id(focus),
+        })
+    }
+}
+
+/// An action requested by assistive technology or a test, dispatched to the engine
+/// with `FlutterEngineDispatchSemanticsAction`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SemanticsActionRequest {
+    pub(crate) node_id: u64,
+    pub(crate) action: FlutterSemanticsAction,
+    pub(crate) data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct SemanticsState {
+    tree: SemanticsTree,
+    /// Whether assistive technology is connected and wants updates.
+    active: bool,
+    /// Whether the tree was requested through [`Semantics::set_enabled`].
+    requested: bool,
+    /// Whether the engine was last told to send semantics updates.
+    enabled: bool,
+    /// Whether the next update must contain the whole tree, because AccessKit
+    /// was activated before the engine sent one.
+    needs_full_tree: bool,
+    /// A change of `enabled` that has not been passed to the engine yet.
+    pending_enabled: Option<bool>,
+    pending_actions: Vec<SemanticsActionRequest>,
+}
+
+impl SemanticsState {
+    fn update_enabled(&mut self) {
+        let enabled = self.active || self.requested;
+        if enabled != self.enabled {
+            self.enabled = enabled;
+            self.pending_enabled = Some(enabled);
+        }
+    }
+
+    fn queue_action(&mut self, id: i32, action: Action, data: Option<ActionData>) -> bool {
+        let (action, data) = match (action, data) {
+            (Action::SetValue, Some(ActionData::Value(value))) => (
+                FlutterSemanticsAction_kFlutterSemanticsActionSetText,
+                encode_message(&EncodableValue::String(value.into())),
+            ),
+            (action, _) => match ACTIONS.iter().find(|(known, _)| *known == action) {
+                Some((_, flutter_action)) => (*flutter_action, Vec::new()),
+                None => return false,
+            },
+        };
+        self.pending_actions.push(SemanticsActionRequest {
+            node_id: id as u64,
+            action,
+            data,
+        });
+        true
+    }
+}
+
+/// The semantics tree of an engine's implicit view.
+///
+/// The tree is built while assistive technology is connected, or after
+/// [`Semantics::set_enabled`], so tests can find nodes and act on them
+/// without a screen reader. Requested actions are passed to the engine when
+/// its platform tasks run, in [`App::run`](crate::application::App::run) or
+/// [`TextureSession::run_platform_tasks`](crate::texture_session::TextureSession::run_platform_tasks).
+#[derive(Clone, Debug, Default)]
+pub struct Semantics {
+    state: Arc<Mutex<SemanticsState>>,
+}
+
+impl Semantics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the engine to build the semantics tree even when no assistive
+    /// technology is connected. The tree is empty until the next frame.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.requested = enabled;
+        state.update_enabled();
+    }
+
+    pub fn root(&self) -> Option<SemanticsNode> {
+        self.node(ROOT_NODE_ID)
+    }
+
+    pub fn node(&self, id: i32) -> Option<SemanticsNode> {
+        self.state.lock().unwrap().tree.nodes.get(&id).cloned()
+    }
+
+    /// Every node of the tree, in traversal order.
+    pub fn nodes(&self) -> Vec<SemanticsNode> {
+        let state = self.state.lock().unwrap();
+        state.tree.traversal().into_iter().cloned().collect()
+    }
+
+    /// The first node in traversal order whose label is `label`.
+    pub fn find_by_label(&self, label: &str) -> Option<SemanticsNode> {
+        let state = self.state.lock().unwrap();
+        state
+            .tree
+            .traversal()
+            .into_iter()
+            .find(|node| node.label == label)
+            .cloned()
+    }
+
+    /// The nodes with `role`, in traversal order.
+    pub fn find_by_role(&self, role: Role) -> Vec<SemanticsNode> {
+        let state = self.state.lock().unwrap();
+        state
+            .tree
+            .traversal()
+            .into_iter()
+            .filter(|node| node.role() == role)
+            .cloned()
+            .collect()
+    }
+
+    /// The bounds of node `id` in physical pixels relative to the top left of
+    /// the window, or of the texture of a texture session.
+    pub fn rect_in_window(&self, id: i32) -> Option<Rect> {
+        self.state.lock().unwrap().tree.rect_in_view(id)
+    }
+
+    /// Taps node `id`, like a screen reader activating it.
+    pub fn tap(&self, id: i32) -> Result<(), AppError> {
+        self.perform_action(id, Action::Click)
+    }
+
+    /// Performs `action` on node `id`. The action must be one of the node's
+    /// [`SemanticsNode::actions`].
+    pub fn perform_action(&self, id: i32, action: Action) -> Result<(), AppError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(node) = state.tree.nodes.get(&id) else {
+            error!("Semantics node {} not found", id);
+            return Err(AppError::UnknownSemanticsNode(id));
+        };
+        if !node.actions().contains(&action) || !state.queue_action(id, action, None) {
+            error!("Semantics node {} does not support {:?}", id, action);
+            return Err(AppError::UnsupportedSemanticsAction(id, action));
+        }
+        Ok(())
+    }
+
+    /// Applies a semantics update and returns the AccessKit update to send.
+    pub(crate) fn apply_update(&self, update: &FlutterSemanticsUpdate2) -> Option<TreeUpdate> {
+        let mut state = self.state.lock().unwrap();
+        let changed = state.tree.apply_update(update);
+        if !state.active {
+            return None;
+        }
+        if std::mem::take(&mut state.needs_full_tree) {
+            state.tree.tree_update(None)
+        } else {
+            state.tree.tree_update(Some(&changed))
+        }
+    }
+
+    /// Takes a change of the semantics enabled state that the engine should be told about.
+    pub(crate) fn take_enabled_change(&self) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+        let enabled = state.pending_enabled.take();
+        if enabled == Some(true) {
+            // The engine sends the whole tree again once semantics are enabled.
+            state.tree.clear();
+        }
+        enabled
+    }
+
+    pub(crate) fn take_actions(&self) -> Vec<SemanticsActionRequest> {
+        std::mem::take(&mut self.state.lock().unwrap().pending_actions)
+    }
+
+    /// Forgets the tree of a Dart isolate that was restarted, and has the
+    /// engine enabled again if semantics are wanted.
+    pub(crate) fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.tree.clear();
+        state.pending_actions.clear();
+        state.needs_full_tree = true;
+        state.enabled = false;
+        state.update_enabled();
+    }
+}
+
+/// The AccessKit adapter of the window that shows the semantics tree.
+pub(crate) struct AccessibilityAdapter(accesskit_winit::Adapter);
+
+impl AccessibilityAdapter {
+    /// Must be called before `window` is first made visible.
+    pub(crate) fn new(window: &Window, semantics: &Semantics) -> Self {
+        Self(accesskit_winit::Adapter::with_direct_handlers(
+            window,
+            AccessKitHandler(semantics.clone()),
+            AccessKitHandler(semantics.clone()),
+            AccessKitHandler(semantics.clone()),
+        ))
+    }
+
+    pub(crate) fn process_event(&mut self, window: &Window, event: &WindowEvent) {
+        self.0.process_event(window, event);
+    }
+
+    pub(crate) fn update(&mut self, update: TreeUpdate) {
+        self.0.update_if_active(|| update);
+    }
+}
+
+impl fmt::Debug for AccessibilityAdapter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessibilityAdapter")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handles AccessKit requests, which arrive on AccessKit's own threads, by
+/// recording them for the session to pass to the engine on the platform thread.
+struct AccessKitHandler(Semantics);
+
+impl ActivationHandler for AccessKitHandler {
+    fn request_initial_tree(&mut self) -> Option<TreeUpdate> {
+        let mut state = self.0.state.lock().unwrap();
+        if !state.active {
+            debug!("Assistive technology connected, enabling semantics");
+            state.active = true;
+            state.update_enabled();
+        }
+        let tree = state.tree.tree_update(None);
+        // Otherwise the tree arrives with the first update after semantics are enabled.
+        state.needs_full_tree = tree.is_none();
+        tree
+    }
+}
+
+impl ActionHandler for AccessKitHandler {
+    fn do_action(&mut self, request: ActionRequest) {
+        let id = request.target.0 as i32;
+        let mut state = self.0.state.lock().unwrap();
+        if !state.queue_action(id, request.action, request.data) {
+            warn!("Unsupported accessibility action {:?}", request.action);
+        }
+    }
+}
+
+impl DeactivationHandler for AccessKitHandler {
+    fn deactivate_accessibility(&mut self) {
+        debug!("Assistive technology disconnected");
+        let mut state = self.0.state.lock().unwrap();
+        state.active = false;
+        state.update_enabled();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    /// A node to send to the tree, turned into a `FlutterSemanticsNode2` by [`apply`].
+    struct TestNode {
+        id: i32,
+        label: &'static str,
+        flags: FlutterSemanticsFlag,
+        actions: FlutterSemanticsAction,
+        rect: FlutterRect,
+        transform: FlutterTransformation,
+        children: Vec<i32>,
+    }
+
+    fn node(id: i32, children: &[i32]) -> TestNode {
+        TestNode {
+            id,
+            label: "",
+            flags: 0,
+            actions: 0,
+            rect: FlutterRect {
+                left: 0.0,
+                top: 0.0,
+                right: 100.0,
+                bottom: 100.0,
+            },
+            transform: transform(1.0, 0.0, 0.0),
+            children: children.to_vec(),
+        }
+    }
+
+    fn transform(scale: f64, x: f64, y: f64) -> FlutterTransformation {
+        FlutterTransformation {
+            scaleX: scale,
+            scaleY: scale,
+            transX: x,
+            transY: y,
+            pers2: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn apply(tree: &mut SemanticsTree, nodes: &[TestNode]) -> Vec<i32> {
+        let labels = nodes
+            .iter()
+            .map(|node| CString::new(node.label).unwrap())
+            .collect::<Vec<_>>();
+        let mut flutter_nodes = nodes
+            .iter()
+            .zip(&labels)
+            .map(|(node, label)| FlutterSemanticsNode2 {
+                struct_size: std::mem::size_of::<FlutterSemanticsNode2>(),
+                id: node.id,
+                flags: node.flags,
+                actions: node.actions,
+                label: label.as_ptr(),
+                rect: node.rect,
+                transform: node.transform,
+                child_count: node.children.len(),
+                children_in_traversal_order: node.children.as_ptr(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let mut pointers = flutter_nodes
+            .iter_mut()
+            .map(|node| node as *mut FlutterSemanticsNode2)
+            .collect::<Vec<_>>();
+        tree.apply_update(&FlutterSemanticsUpdate2 {
+            struct_size: std::mem::size_of::<FlutterSemanticsUpdate2>(),
+            node_count: pointers.len(),
+            nodes: pointers.as_mut_ptr(),
+            ..Default::default()
+        })
+    }
+
+    fn ids(nodes: &[SemanticsNode]) -> Vec<i32> {
+        nodes.iter().map(SemanticsNode::id).collect()
+    }
+
+    #[test]
+    fn prunes_unreachable_nodes() {
+        let semantics = Semantics::new();
+        let mut state = semantics.state.lock().unwrap();
+        let changed = apply(
+            &mut state.tree,
+            &[node(0, &[1, 2]), node(1, &[3]), node(2, &[]), node(3, &[])],
+        );
+        assert_eq!(changed, [0, 1, 2, 3]);
+
+        // Node 1 is detached from the root, taking node 3 with it, and node 4
+        // never gets a parent.
+        let changed = apply(&mut state.tree, &[node(0, &[2]), node(4, &[])]);
+        assert_eq!(changed, [0]);
+        drop(state);
+        assert_eq!(ids(&semantics.nodes()), [0, 2]);
+        assert!(semantics.node(1).is_none());
+        assert!(semantics.node(3).is_none());
+        assert!(semantics.node(4).is_none());
+    }
+
+    #[test]
+    fn nested_transforms_map_to_the_window() {
+        let semantics = Semantics::new();
+        apply(
+            &mut semantics.state.lock().unwrap().tree,
+            &[
+                node(0, &[1]),
+                TestNode {
+                    transform: transform(2.0, 10.0, 20.0),
+                    ..node(1, &[2])
+                },
+                TestNode {
+                    rect: FlutterRect {
+                        left: 0.0,
+                        top: 0.0,
+                        right: 10.0,
+                        bottom: 4.0,
+                    },
+                    transform: transform(1.0, 5.0, 5.0),
+                    ..node(2, &[])
+                },
+            ],
+        );
+        assert_eq!(
+            semantics.rect_in_window(2),
+            Some(Rect::new(20.0, 30.0, 40.0, 38.0))
+        );
+        assert_eq!(
+            semantics.rect_in_window(1),
+            Some(Rect::new(10.0, 20.0, 210.0, 220.0))
+        );
+        assert_eq!(semantics.rect_in_window(3), None);
+    }
+
+    #[test]
+    fn finds_nodes_by_label_and_role() {
+        let semantics = Semantics::new();
+        let button = |id, label| TestNode {
+            label,
+            flags: FlutterSemanticsFlag_kFlutterSemanticsFlagIsButton,
+            ..node(id, &[])
+        };
+        apply(
+            &mut semantics.state.lock().unwrap().tree,
+            &[
+                node(0, &[1, 2, 3]),
+                button(1, "OK"),
+                TestNode {
+                    label: "Title",
+                    ..node(2, &[])
+                },
+                button(3, "Cancel"),
+            ],
+        );
+        assert_eq!(
+            semantics.find_by_label("Cancel").map(|node| node.id()),
+            Some(3)
+        );
+        assert_eq!(
+            semantics.find_by_label("Title").map(|node| node.id()),
+            Some(2)
+        );
+        assert!(semantics.find_by_label("Missing").is_none());
+        assert_eq!(ids(&semantics.find_by_role(Role::Button)), [1, 3]);
+        assert_eq!(ids(&semantics.find_by_role(Role::Label)), [2]);
+        assert_eq!(ids(&semantics.find_by_role(Role::Window)), [0]);
+        assert!(semantics.find_by_role(Role::Slider).is_empty());
+    }
+
+    #[test]
+    fn rejects_unsupported_actions() {
+        let semantics = Semantics::new();
+        apply(
+            &mut semantics.state.lock().unwrap().tree,
+            &[
+                node(0, &[1]),
+                TestNode {
+                    actions: FlutterSemanticsAction_kFlutterSemanticsActionTap,
+                    ..node(1, &[])
+                },
+            ],
+        );
+        assert!(matches!(
+            semantics.perform_action(1, Action::ScrollDown),
+            Err(AppError::UnsupportedSemanticsAction(1, Action::ScrollDown))
+        ));
+        assert!(matches!(
+            semantics.perform_action(2, Action::Click),
+            Err(AppError::UnknownSemanticsNode(2))
+        ));
+        assert!(semantics.take_actions().is_empty());
+
+        semantics.tap(1).unwrap();
+        assert_eq!(
+            semantics.take_actions(),
+            [SemanticsActionRequest {
+                node_id: 1,
+                action: FlutterSemanticsAction_kFlutterSemanticsActionTap,
+                data: Vec::new(),
+            }]
+        );
+    }
+}
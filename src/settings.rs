@@ -0,0 +1,107 @@
+//! User preferences reported to Flutter, such as dark mode and text scale.
+
+use std::fmt;
+
+use winit::window::Theme;
+
+use crate::flutter_embedder::*;
+
+/// The channel the engine receives platform settings on, as JSON.
+pub(crate) const SETTINGS_CHANNEL: &str = "flutter/settings";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Brightness {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// The values Flutter exposes through `MediaQuery` and `PlatformDispatcher`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SystemSettings {
+    pub brightness: Brightness,
+    pub text_scale_factor: f64,
+    pub always_use_24_hour_format: bool,
+    pub high_contrast: bool,
+    /// Reported as both disabled animations and reduced motion.
+    pub reduce_motion: bool,
+    pub bold_text: bool,
+}
+
+impl Default for SystemSettings {
+    fn default() -> Self {
+        Self {
+            brightness: Brightness::Light,
+            text_scale_factor: 1.0,
+            always_use_24_hour_format: false,
+            high_contrast: false,
+            reduce_motion: false,
+            bold_text: false,
+        }
+    }
+}
+
+impl SystemSettings {
+    /// The `flutter/settings` message.
+    pub(crate) fn to_json(&self) -> String {
+        let brightness = match self.brightness {
+            Brightness::Light => "light",
+            Brightness::Dark => "dark",
+        };
+        let text_scale_factor = if self.text_scale_factor.is_finite() {
+            self.text_scale_factor
+        } else {
+            1.0
+        };
+        format!(
+            r#"{{"textScaleFactor":{},"alwaysUse24HourFormat":{},"platformBrightness":"{}"}}"#,
+            text_scale_factor, self.always_use_24_hour_format, brightness
+        )
+    }
+
+    pub(crate) fn accessibility_features(&self) -> FlutterAccessibilityFeature {
+        let mut features = 0;
+        if self.high_contrast {
+            features |= FlutterAccessibilityFeature_kFlutterAccessibilityFeatureHighContrast;
+        }
+        if self.reduce_motion {
+            features |= FlutterAccessibilityFeature_kFlutterAccessibilityFeatureDisableAnimations
+                | FlutterAccessibilityFeature_kFlutterAccessibilityFeatureReduceMotion;
+        }
+        if self.bold_text {
+            features |= FlutterAccessibilityFeature_kFlutterAccessibilityFeatureBoldText;
+        }
+        features
+    }
+}
+
+/// Provides the settings reported to an engine. They are read when the engine
+/// starts and whenever the OS theme of one of its windows changes.
+pub trait SystemSettingsProvider: fmt::Debug + Send + Sync {
+    /// `window_theme` is the OS theme of the window, if the platform reports one.
+    fn system_settings(&self, window_theme: Option<Theme>) -> SystemSettings;
+}
+
+/// Fixed settings, for tests or apps that ignore the OS preferences.
+impl SystemSettingsProvider for SystemSettings {
+    fn system_settings(&self, _window_theme: Option<Theme>) -> SystemSettings {
+        self.clone()
+    }
+}
+
+/// Follows the OS theme. Other settings are not exposed by the windowing
+/// system and keep their defaults.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsSettings;
+
+impl SystemSettingsProvider for OsSettings {
+    fn system_settings(&self, window_theme: Option<Theme>) -> SystemSettings {
+        SystemSettings {
+            brightness: match window_theme {
+                Some(Theme::Dark) => Brightness::Dark,
+                Some(Theme::Light) | None => Brightness::Light,
+            },
+            ..Default::default()
+        }
+    }
+}
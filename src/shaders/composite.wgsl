@@ -0,0 +1,59 @@
+// Draws a single Flutter layer as a textured quad.
+// The quad spans (0, 0) - (1, 1) and is positioned by `layer.transform`.
+
+struct LayerUniforms {
+    transform: mat4x4<f32>,
+    opacity: f32,
+};
+
+// Set for linear targets such as float surfaces. Flutter's pixels are sRGB
+// encoded and are otherwise copied through unchanged.
+override decode_srgb: bool = false;
+
+@group(0) @binding(0) var<uniform> layer: LayerUniforms;
+@group(0) @binding(1) var layer_texture: texture_2d<f32>;
+@group(0) @binding(2) var layer_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32(index & 1u), f32((index >> 1u) & 1u));
+    var out: VertexOutput;
+    out.position = layer.transform * vec4<f32>(uv, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(layer_texture, layer_sampler, in.uv);
+    if decode_srgb && color.a > 0.0 {
+        // The encoding applies to the straight color, not the premultiplied one.
+        color = vec4<f32>(srgb_to_linear(color.rgb / color.a) * color.a, color.a);
+    }
+    // Flutter renders premultiplied alpha, so opacity scales all channels.
+    return color * layer.opacity;
+}
+
+// Covers the whole target; the scissor rect limits it to the repainted part.
+@vertex
+fn vs_clear(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32(index & 1u), f32((index >> 1u) & 1u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Drawn without blending, so it clears to transparent.
+@fragment
+fn fs_clear() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0);
+}
@@ -0,0 +1,26 @@
+// Draws the solid rects of the performance overlay, one instance per rect.
+// Each rect is given by its corners in clip space.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) rect: vec4<f32>,
+    @location(1) color: vec4<f32>,
+) -> VertexOutput {
+    let corner = vec2<f32>(f32(index & 1u), f32((index >> 1u) & 1u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(mix(rect.xy, rect.zw, corner), 0.0, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Premultiplied, like the Flutter layers below.
+    return vec4<f32>(in.color.rgb * in.color.a, in.color.a);
+}
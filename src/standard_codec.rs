@@ -0,0 +1,516 @@
+//! Flutter's `StandardMessageCodec` and `StandardMethodCodec` wire format.
+
+use std::fmt;
+
+const NULL: u8 = 0;
+const TRUE: u8 = 1;
+const FALSE: u8 = 2;
+const INT32: u8 = 3;
+const INT64: u8 = 4;
+const LARGE_INT: u8 = 5;
+const FLOAT64: u8 = 6;
+const STRING: u8 = 7;
+const UINT8_LIST: u8 = 8;
+const INT32_LIST: u8 = 9;
+const INT64_LIST: u8 = 10;
+const FLOAT64_LIST: u8 = 11;
+const LIST: u8 = 12;
+const MAP: u8 = 13;
+const FLOAT32_LIST: u8 = 14;
+
+/// A value that can be sent over a standard codec channel.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncodableValue {
+    Null,
+    Bool(bool),
+    Int32(i32),
+    Int64(i64),
+    Float64(f64),
+    String(String),
+    Uint8List(Vec<u8>),
+    Int32List(Vec<i32>),
+    Int64List(Vec<i64>),
+    Float64List(Vec<f64>),
+    Float32List(Vec<f32>),
+    List(Vec<EncodableValue>),
+    Map(Vec<(EncodableValue, EncodableValue)>),
+}
+
+impl EncodableValue {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int32(value) => Some(*value as i64),
+            Self::Int64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Float64(value) => Some(*value),
+            _ => self.as_i64().map(|value| value as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Looks up a string key in a map value.
+    pub fn get(&self, key: &str) -> Option<&EncodableValue> {
+        let Self::Map(entries) = self else {
+            return None;
+        };
+        entries
+            .iter()
+            .find(|(entry_key, _)| entry_key.as_str() == Some(key))
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError(&'static str);
+
+impl DecodeError {
+    pub(crate) const fn new(reason: &'static str) -> Self {
+        Self(reason)
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed platform channel message: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A method invocation decoded from a `StandardMethodCodec` channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodCall {
+    pub method: String,
+    pub arguments: EncodableValue,
+}
+
+impl MethodCall {
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader { bytes, position: 0 };
+        let EncodableValue::String(method) = reader.read_value()? else {
+            return Err(DecodeError("method name is not a string"));
+        };
+        let arguments = if reader.position < bytes.len() {
+            reader.read_value()?
+        } else {
+            EncodableValue::Null
+        };
+        Ok(Self { method, arguments })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.write_value(&EncodableValue::String(self.method.clone()));
+        writer.write_value(&self.arguments);
+        writer.bytes
+    }
+}
+
+pub fn encode_message(value: &EncodableValue) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.write_value(value);
+    writer.bytes
+}
+
+pub fn decode_message(bytes: &[u8]) -> Result<EncodableValue, DecodeError> {
+    Reader { bytes, position: 0 }.read_value()
+}
+
+/// Encodes a successful method call result.
+pub fn encode_success_envelope(result: &EncodableValue) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes.push(0);
+    writer.write_value(result);
+    writer.bytes
+}
+
+/// Encodes a failed method call, which Dart receives as a `PlatformException`.
+pub fn encode_error_envelope(code: &str, message: Option<&str>) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes.push(1);
+    writer.write_value(&EncodableValue::String(code.to_string()));
+    writer.write_value(&message.map_or(EncodableValue::Null, |message| {
+        EncodableValue::String(message.to_string())
+    }));
+    writer.write_value(&EncodableValue::Null);
+    writer.bytes
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn write_size(&mut self, size: usize) {
+        if size < 254 {
+            self.bytes.push(size as u8);
+        } else if size <= u16::MAX as usize {
+            self.bytes.push(254);
+            self.bytes.extend_from_slice(&(size as u16).to_le_bytes());
+        } else {
+            self.bytes.push(255);
+            self.bytes.extend_from_slice(&(size as u32).to_le_bytes());
+        }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        while !self.bytes.len().is_multiple_of(alignment) {
+            self.bytes.push(0);
+        }
+    }
+
+    fn write_value(&mut self, value: &EncodableValue) {
+        match value {
+            EncodableValue::Null => self.bytes.push(NULL),
+            EncodableValue::Bool(true) => self.bytes.push(TRUE),
+            EncodableValue::Bool(false) => self.bytes.push(FALSE),
+            EncodableValue::Int32(value) => {
+                self.bytes.push(INT32);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            EncodableValue::Int64(value) => {
+                self.bytes.push(INT64);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            EncodableValue::Float64(value) => {
+                self.bytes.push(FLOAT64);
+                self.align(8);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            EncodableValue::String(value) => {
+                self.bytes.push(STRING);
+                self.write_size(value.len());
+                self.bytes.extend_from_slice(value.as_bytes());
+            }
+            EncodableValue::Uint8List(values) => {
+                self.bytes.push(UINT8_LIST);
+                self.write_size(values.len());
+                self.bytes.extend_from_slice(values);
+            }
+            EncodableValue::Int32List(values) => {
+                self.bytes.push(INT32_LIST);
+                self.write_size(values.len());
+                self.align(4);
+                values
+                    .iter()
+                    .for_each(|value| self.bytes.extend_from_slice(&value.to_le_bytes()));
+            }
+            EncodableValue::Int64List(values) => {
+                self.bytes.push(INT64_LIST);
+                self.write_size(values.len());
+                self.align(8);
+                values
+                    .iter()
+                    .for_each(|value| self.bytes.extend_from_slice(&value.to_le_bytes()));
+            }
+            EncodableValue::Float64List(values) => {
+                self.bytes.push(FLOAT64_LIST);
+                self.write_size(values.len());
+                self.align(8);
+                values
+                    .iter()
+                    .for_each(|value| self.bytes.extend_from_slice(&value.to_le_bytes()));
+            }
+            EncodableValue::Float32List(values) => {
+                self.bytes.push(FLOAT32_LIST);
+                self.write_size(values.len());
+                self.align(4);
+                values
+                    .iter()
+                    .for_each(|value| self.bytes.extend_from_slice(&value.to_le_bytes()));
+            }
+            EncodableValue::List(values) => {
+                self.bytes.push(LIST);
+                self.write_size(values.len());
+                values.iter().for_each(|value| self.write_value(value));
+            }
+            EncodableValue::Map(entries) => {
+                self.bytes.push(MAP);
+                self.write_size(entries.len());
+                for (key, value) in entries {
+                    self.write_value(key);
+                    self.write_value(value);
+                }
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn read_bytes(&mut self, count: usize) -> Result<&[u8], DecodeError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(DecodeError("unexpected end of message"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+
+    fn read_size(&mut self) -> Result<usize, DecodeError> {
+        match self.read_array::<1>()?[0] {
+            254 => Ok(u16::from_le_bytes(self.read_array()?) as usize),
+            255 => Ok(u32::from_le_bytes(self.read_array()?) as usize),
+            size => Ok(size as usize),
+        }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        let padding = (alignment - self.position % alignment) % alignment;
+        self.position += padding;
+    }
+
+    fn read_list<T, const N: usize>(
+        &mut self,
+        convert: fn([u8; N]) -> T,
+    ) -> Result<Vec<T>, DecodeError> {
+        let count = self.read_size()?;
+        self.align(N);
+        (0..count)
+            .map(|_| Ok(convert(self.read_array()?)))
+            .collect()
+    }
+
+    fn read_value(&mut self) -> Result<EncodableValue, DecodeError> {
+        let value = match self.read_array::<1>()?[0] {
+            NULL => EncodableValue::Null,
+            TRUE => EncodableValue::Bool(true),
+            FALSE => EncodableValue::Bool(false),
+            INT32 => EncodableValue::Int32(i32::from_le_bytes(self.read_array()?)),
+            INT64 => EncodableValue::Int64(i64::from_le_bytes(self.read_array()?)),
+            LARGE_INT | STRING => {
+                let size = self.read_size()?;
+                let bytes = self.read_bytes(size)?;
+                let value = std::str::from_utf8(bytes)
+                    .map_err(|_| DecodeError("string is not valid UTF-8"))?;
+                EncodableValue::String(value.to_string())
+            }
+            FLOAT64 => {
+                self.align(8);
+                EncodableValue::Float64(f64::from_le_bytes(self.read_array()?))
+            }
+            UINT8_LIST => {
+                let size = self.read_size()?;
+                EncodableValue::Uint8List(self.read_bytes(size)?.to_vec())
+            }
+            INT32_LIST => EncodableValue::Int32List(self.read_list(i32::from_le_bytes)?),
+            INT64_LIST => EncodableValue::Int64List(self.read_list(i64::from_le_bytes)?),
+            FLOAT64_LIST => EncodableValue::Float64List(self.read_list(f64::from_le_bytes)?),
+            FLOAT32_LIST => EncodableValue::Float32List(self.read_list(f32::from_le_bytes)?),
+            LIST => {
+                let count = self.read_size()?;
+                EncodableValue::List(
+                    (0..count)
+                        .map(|_| self.read_value())
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            MAP => {
+                let count = self.read_size()?;
+                EncodableValue::Map(
+                    (0..count)
+                        .map(|_| Ok((self.read_value()?, self.read_value()?)))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            _ => return Err(DecodeError("unknown value type")),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A string of `len` ASCII bytes and its encoding.
+    fn string_fixture(len: usize, size: &[u8]) -> (EncodableValue, Vec<u8>) {
+        let value = "x".repeat(len);
+        let mut bytes = vec![STRING];
+        bytes.extend_from_slice(size);
+        bytes.extend_from_slice(value.as_bytes());
+        (EncodableValue::String(value), bytes)
+    }
+
+    fn assert_wire_format(value: &EncodableValue, bytes: &[u8]) {
+        assert_eq!(encode_message(value), bytes);
+        assert_eq!(decode_message(bytes).as_ref(), Ok(value));
+    }
+
+    #[test]
+    fn encodes_scalars() {
+        assert_wire_format(&EncodableValue::Null, &[0]);
+        assert_wire_format(&EncodableValue::Bool(true), &[1]);
+        assert_wire_format(&EncodableValue::Bool(false), &[2]);
+        assert_wire_format(&EncodableValue::Int32(-2), &[3, 0xfe, 0xff, 0xff, 0xff]);
+        assert_wire_format(
+            &EncodableValue::Int64(1 << 32),
+            &[4, 0, 0, 0, 0, 1, 0, 0, 0],
+        );
+        assert_wire_format(
+            &EncodableValue::String("hé".to_string()),
+            &[7, 3, b'h', 0xc3, 0xa9],
+        );
+    }
+
+    #[test]
+    fn aligns_float64() {
+        assert_wire_format(
+            &EncodableValue::Float64(2.5),
+            &[6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x04, 0x40],
+        );
+        // The envelope byte counts towards the alignment.
+        assert_eq!(
+            encode_success_envelope(&EncodableValue::Float64(1.0)),
+            [0, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f]
+        );
+        assert_wire_format(
+            &EncodableValue::List(vec![EncodableValue::Float64(1.0)]),
+            &[12, 1, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f],
+        );
+    }
+
+    #[test]
+    fn encodes_sizes() {
+        for (len, size) in [
+            (253, &[253][..]),
+            (254, &[254, 254, 0][..]),
+            (255, &[254, 255, 0][..]),
+            (65535, &[254, 0xff, 0xff][..]),
+            (65536, &[255, 0, 0, 1, 0][..]),
+        ] {
+            let (value, bytes) = string_fixture(len, size);
+            assert_wire_format(&value, &bytes);
+        }
+    }
+
+    #[test]
+    fn encodes_typed_lists() {
+        assert_wire_format(&EncodableValue::Uint8List(vec![1, 2, 3]), &[8, 3, 1, 2, 3]);
+        assert_wire_format(
+            &EncodableValue::Int32List(vec![1, -1]),
+            &[9, 2, 0, 0, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff],
+        );
+        assert_wire_format(
+            &EncodableValue::Int64List(vec![2]),
+            &[10, 1, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0],
+        );
+        assert_wire_format(
+            &EncodableValue::Float64List(vec![1.0]),
+            &[11, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f],
+        );
+        assert_wire_format(
+            &EncodableValue::Float32List(vec![1.0, 2.0]),
+            &[14, 2, 0, 0, 0, 0, 0x80, 0x3f, 0, 0, 0, 0x40],
+        );
+        assert_wire_format(&EncodableValue::Int32List(Vec::new()), &[9, 0, 0, 0]);
+    }
+
+    #[test]
+    fn encodes_nested_maps() {
+        let value = EncodableValue::Map(vec![
+            (
+                EncodableValue::String("a".to_string()),
+                EncodableValue::List(vec![EncodableValue::Int32(1), EncodableValue::Null]),
+            ),
+            (
+                EncodableValue::String("b".to_string()),
+                EncodableValue::Map(vec![(EncodableValue::Int32(2), EncodableValue::Bool(true))]),
+            ),
+        ]);
+        assert_wire_format(
+            &value,
+            &[
+                13, 2, 7, 1, b'a', 12, 2, 3, 1, 0, 0, 0, 0, 7, 1, b'b', 13, 1, 3, 2, 0, 0, 0, 1,
+            ],
+        );
+        assert_eq!(
+            value.get("b"),
+            Some(&EncodableValue::Map(vec![(
+                EncodableValue::Int32(2),
+                EncodableValue::Bool(true)
+            )]))
+        );
+    }
+
+    #[test]
+    fn decodes_method_calls() {
+        // StandardMethodCodec().encodeMethodCall(MethodCall('create', {'id': 1}))
+        let bytes = [
+            7, 6, b'c', b'r', b'e', b'a', b't', b'e', 13, 1, 7, 2, b'i', b'd', 3, 1, 0, 0, 0,
+        ];
+        let call = MethodCall::decode(&bytes).unwrap();
+        assert_eq!(call.method, "create");
+        assert_eq!(
+            call.arguments.get("id").and_then(EncodableValue::as_i64),
+            Some(1)
+        );
+        assert_eq!(call.encode(), bytes);
+        assert_eq!(
+            MethodCall::decode(&[7, 4, b'p', b'i', b'n', b'g']).unwrap(),
+            MethodCall {
+                method: "ping".to_string(),
+                arguments: EncodableValue::Null,
+            }
+        );
+        assert!(MethodCall::decode(&[3, 1, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn encodes_error_envelopes() {
+        assert_eq!(
+            encode_error_envelope("bad", Some("no")),
+            [1, 7, 3, b'b', b'a', b'd', 7, 2, b'n', b'o', 0]
+        );
+        assert_eq!(
+            encode_error_envelope("bad", None),
+            [1, 7, 3, b'b', b'a', b'd', 0, 0]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let value = EncodableValue::Map(vec![(
+            EncodableValue::String("values".to_string()),
+            EncodableValue::List(vec![
+                EncodableValue::Float64(1.5),
+                EncodableValue::Int64List(vec![1, 2]),
+                EncodableValue::Uint8List(vec![1; 300]),
+            ]),
+        )]);
+        let bytes = encode_message(&value);
+        for len in 0..bytes.len() {
+            assert!(
+                decode_message(&bytes[..len]).is_err(),
+                "decoded a prefix of {} bytes",
+                len
+            );
+        }
+        assert!(decode_message(&[255, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode_message(&[12, 255, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode_message(&[7, 2, 0xff, 0xfe]).is_err());
+        assert!(decode_message(&[15]).is_err());
+    }
+}
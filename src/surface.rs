@@ -0,0 +1,131 @@
+//! How window surfaces are configured: present mode, alpha mode, frame
+//! latency and format, each checked against what the surface supports.
+
+use tracing::warn;
+
+/// The kind of format to present in.
+///
+/// Flutter renders sRGB encoded pixels, which are copied unchanged into 8 and
+/// 10 bit formats, since displays treat those as sRGB encoded as well. Float
+/// formats are linear (scRGB), so the pixels are decoded for them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SurfaceFormat {
+    /// The format the surface lists first, which is its preferred one.
+    #[default]
+    Preferred,
+    /// 8 bits per channel with an sRGB format, like `Bgra8UnormSrgb`.
+    Srgb,
+    /// 8 bits per channel with a plain format, like `Bgra8Unorm`.
+    Linear,
+    /// 10 bits per color channel, `Rgb10a2Unorm`.
+    Hdr10,
+    /// Half floats, `Rgba16Float`.
+    HdrFloat,
+}
+
+impl SurfaceFormat {
+    fn candidates(self) -> &'static [wgpu::TextureFormat] {
+        use wgpu::TextureFormat::*;
+        match self {
+            SurfaceFormat::Preferred => &[],
+            SurfaceFormat::Srgb => &[Bgra8UnormSrgb, Rgba8UnormSrgb],
+            SurfaceFormat::Linear => &[Bgra8Unorm, Rgba8Unorm],
+            SurfaceFormat::Hdr10 => &[Rgb10a2Unorm],
+            SurfaceFormat::HdrFloat => &[Rgba16Float],
+        }
+    }
+}
+
+/// Options for the surfaces of an engine's windows. Options a surface does
+/// not support fall back to the defaults, with a warning.
+#[derive(Clone, Debug)]
+pub struct SurfaceOptions {
+    /// Such as `Mailbox`, `Immediate` or `FifoRelaxed`. Falls back to `Fifo`,
+    /// which every surface supports.
+    pub present_mode: wgpu::PresentMode,
+    /// Falls back to `Auto`, which picks a supported mode.
+    pub alpha_mode: wgpu::CompositeAlphaMode,
+    /// The number of frames queued for the display, at least 1. Higher values
+    /// smooth out uneven frames at the cost of input latency.
+    pub frame_latency: u32,
+    /// Falls back to [`SurfaceFormat::Preferred`].
+    pub format: SurfaceFormat,
+}
+
+impl Default for SurfaceOptions {
+    fn default() -> Self {
+        Self {
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            frame_latency: 1,
+            format: SurfaceFormat::Preferred,
+        }
+    }
+}
+
+impl SurfaceOptions {
+    /// The configuration of a surface with `capabilities`, or `None` if the
+    /// surface supports no format at all.
+    pub(crate) fn configuration(
+        &self,
+        capabilities: &wgpu::SurfaceCapabilities,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Option<wgpu::SurfaceConfiguration> {
+        let preferred = *capabilities.formats.first()?;
+        let format = self
+            .format
+            .candidates()
+            .iter()
+            .copied()
+            .find(|format| capabilities.formats.contains(format))
+            .unwrap_or_else(|| {
+                if self.format != SurfaceFormat::Preferred {
+                    warn!(
+                        "The surface supports no {:?} format, presenting in {:?}",
+                        self.format, preferred
+                    );
+                }
+                preferred
+            });
+
+        // The automatic modes are resolved by wgpu.
+        let present_mode = match self.present_mode {
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => self.present_mode,
+            mode if capabilities.present_modes.contains(&mode) => mode,
+            mode => {
+                warn!("The surface does not support {:?}, using Fifo", mode);
+                wgpu::PresentMode::Fifo
+            }
+        };
+
+        let alpha_mode = match self.alpha_mode {
+            wgpu::CompositeAlphaMode::Auto => self.alpha_mode,
+            mode if capabilities.alpha_modes.contains(&mode) => mode,
+            mode => {
+                warn!("The surface does not support {:?} alpha, using Auto", mode);
+                wgpu::CompositeAlphaMode::Auto
+            }
+        };
+
+        Some(wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            // Request compatibility with the non-sRGB view the layers are drawn through.
+            view_formats: vec![format.remove_srgb_suffix()],
+            alpha_mode,
+            width: size.width,
+            height: size.height,
+            desired_maximum_frame_latency: self.frame_latency.max(1),
+            present_mode,
+        })
+    }
+}
+
+/// Whether pixels drawn into `format` are taken as linear rather than sRGB
+/// encoded, so Flutter's sRGB encoded pixels need decoding.
+pub(crate) fn is_linear(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float
+    )
+}
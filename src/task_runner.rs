@@ -0,0 +1,105 @@
+use std::sync::Mutex;
+use std::thread::ThreadId;
+
+use crate::flutter_embedder::{FlutterTask, FlutterTaskRunnerDescription};
+use crate::utils::catch_callback_panic;
+
+/// A task posted by the engine, to be handed back through `FlutterEngineRunTask`
+/// on the platform thread once `target_time` has passed.
+#[derive(Debug)]
+struct PendingTask {
+    task: FlutterTask,
+    target_time: u64,
+}
+
+// The task runner pointer is opaque and only handed back to the engine.
+unsafe impl Send for PendingTask {}
+
+/// Runs the engine's platform tasks on the thread that created the session.
+///
+/// Without a custom platform task runner the engine posts platform tasks,
+/// including platform message delivery, to a message loop that never runs.
+/// The session drains this queue from its own event loop instead.
+#[derive(Debug)]
+pub(crate) struct PlatformTaskRunner {
+    thread: ThreadId,
+    tasks: Mutex<Vec<PendingTask>>,
+    /// Batons of the engine's vsync requests, answered on the platform thread.
+    vsync_batons: Mutex<Vec<isize>>,
+}
+
+impl PlatformTaskRunner {
+    pub(crate) fn new() -> Self {
+        Self {
+            thread: std::thread::current().id(),
+            tasks: Mutex::new(Vec::new()),
+            vsync_batons: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The description passed to the engine. `self` must not move while the engine runs.
+    pub(crate) fn description(&self) -> FlutterTaskRunnerDescription {
+        FlutterTaskRunnerDescription {
+            struct_size: size_of::<FlutterTaskRunnerDescription>(),
+            user_data: self as *const Self as *mut ::core::ffi::c_void,
+            runs_task_on_current_thread_callback: Some(Self::runs_task_on_current_thread_callback),
+            post_task_callback: Some(Self::post_task_callback),
+            identifier: 1,
+        }
+    }
+
+    /// Removes and returns the tasks that are due at `now`, in target time order.
+    pub(crate) fn take_expired(&self, now: u64) -> Vec<FlutterTask> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let mut expired = Vec::new();
+        tasks.retain(|pending| {
+            if pending.target_time <= now {
+                expired.push((pending.target_time, pending.task));
+                false
+            } else {
+                true
+            }
+        });
+        expired.sort_by_key(|(target_time, _)| *target_time);
+        expired.into_iter().map(|(_, task)| task).collect()
+    }
+
+    /// Queues a vsync request from any thread, as `FlutterEngineOnVsync` must
+    /// be called on the platform thread.
+    pub(crate) fn post_vsync(&self, baton: isize) {
+        self.vsync_batons.lock().unwrap().push(baton);
+    }
+
+    /// Removes and returns the batons of the queued vsync requests.
+    pub(crate) fn take_vsync_batons(&self) -> Vec<isize> {
+        std::mem::take(&mut *self.vsync_batons.lock().unwrap())
+    }
+
+    /// Drops the pending tasks, which belong to an engine that was shut down.
+    pub(crate) fn clear(&self) {
+        self.tasks.lock().unwrap().clear();
+        self.vsync_batons.lock().unwrap().clear();
+    }
+
+    extern "C" fn runs_task_on_current_thread_callback(
+        user_data: *mut ::core::ffi::c_void,
+    ) -> bool {
+        let runner = unsafe { &*(user_data as *const PlatformTaskRunner) };
+        std::thread::current().id() == runner.thread
+    }
+
+    extern "C" fn post_task_callback(
+        task: FlutterTask,
+        target_time: u64,
+        user_data: *mut ::core::ffi::c_void,
+    ) {
+        catch_callback_panic("post_task_callback", (), || {
+            let runner = unsafe { &*(user_data as *const PlatformTaskRunner) };
+            runner
+                .tasks
+                .lock()
+                .unwrap()
+                .push(PendingTask { task, target_time });
+        })
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tracing::{error, info};
+use winit::dpi::PhysicalSize;
+
+use crate::application::AppError;
+use crate::flutter_embedder::{
+    FlutterEngine, FlutterEngineProcTable, FlutterEngineResult_kSuccess,
+    FlutterEngineScheduleFrameFnPtr,
+};
+use crate::platform_views::{PlatformViewFactory, PlatformViewRegistry};
+use crate::standard_codec::EncodableValue;
+
+/// The platform view type Dart shows registered textures with, passing
+/// `{"textureId": id}` as the creation params.
+pub const TEXTURE_VIEW_TYPE: &str = "flutter_rust_embedder/texture";
+
+/// Produces the texture for the next frame, given the size Flutter lays it out at.
+pub type TextureProducer = Box<dyn FnMut(u32, u32) -> Option<wgpu::Texture> + Send>;
+
+/// The content behind an external texture id.
+pub enum ExternalTexture {
+    /// A fixed texture. Replace it with [`TextureRegistry::update_texture`].
+    Texture(wgpu::Texture),
+    /// A callback asked for a texture every frame the id is shown in.
+    Producer(TextureProducer),
+}
+
+impl fmt::Debug for ExternalTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Texture(texture) => f.debug_tuple("Texture").field(texture).finish(),
+            Self::Producer(_) => f.write_str("Producer"),
+        }
+    }
+}
+
+/// Schedules a frame of a running engine, so a new texture is drawn. It may
+/// be called from any thread.
+struct EngineBinding {
+    engine: FlutterEngine,
+    schedule_frame: FlutterEngineScheduleFrameFnPtr,
+}
+
+// The engine handle is only passed back to the thread safe `FlutterEngineScheduleFrame`.
+unsafe impl Send for EngineBinding {}
+
+impl EngineBinding {
+    fn schedule_frame(&self) -> Result<(), AppError> {
+        let Some(schedule_frame) = self.schedule_frame else {
+            error!("FlutterEngineScheduleFrame not found");
+            return Err(AppError::FlutterEngineProcTable(
+                "FlutterEngineScheduleFrame".to_string(),
+            ));
+        };
+        let res = unsafe { schedule_frame(self.engine) };
+        if res != FlutterEngineResult_kSuccess {
+            error!("FlutterEngineScheduleFrame failed: {}", res);
+            return Err(AppError::FlutterEngineError(res));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct RegistryState {
+    engine: Option<EngineBinding>,
+    last_id: i64,
+    textures: HashMap<i64, ExternalTexture>,
+}
+
+/// Registers textures that Dart code can show as a platform view of type
+/// [`TEXTURE_VIEW_TYPE`], created with the params `{"textureId": id}`.
+///
+/// `FlutterVulkanRendererConfig` has no external texture callback in the
+/// embedder API this crate is built against, so `Texture(textureId: id)`
+/// cannot draw these textures and the ids are not registered with the engine.
+/// The compositor draws them like any other platform view instead, so they
+/// must come from the engine's device and have `TEXTURE_BINDING` usage.
+///
+/// The registry can be cloned and used from any thread, and can be used before
+/// the engine starts.
+#[derive(Clone, Default)]
+pub struct TextureRegistry {
+    state: Arc<Mutex<RegistryState>>,
+}
+
+impl fmt::Debug for TextureRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("TextureRegistry")
+            .field("bound", &state.engine.is_some())
+            .field("ids", &state.textures.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl TextureRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a fixed texture and returns its id.
+    pub fn register_texture(&self, texture: wgpu::Texture) -> i64 {
+        self.register(ExternalTexture::Texture(texture))
+    }
+
+    /// Registers a callback that provides a texture for each frame, e.g. a video
+    /// decoder, and returns its id.
+    pub fn register_producer(
+        &self,
+        producer: impl FnMut(u32, u32) -> Option<wgpu::Texture> + Send + 'static,
+    ) -> i64 {
+        self.register(ExternalTexture::Producer(Box::new(producer)))
+    }
+
+    fn register(&self, texture: ExternalTexture) -> i64 {
+        let mut state = self.state.lock().unwrap();
+        state.last_id += 1;
+        let id = state.last_id;
+        state.textures.insert(id, texture);
+        id
+    }
+
+    /// Removes the texture. Views still showing the id are left out of frames.
+    pub fn unregister(&self, id: i64) -> Result<(), AppError> {
+        let mut state = self.state.lock().unwrap();
+        if state.textures.remove(&id).is_none() {
+            return Err(AppError::UnknownTexture(id));
+        }
+        Ok(())
+    }
+
+    /// Tells the engine the texture has new content, so a frame is drawn with it.
+    pub fn mark_frame_available(&self, id: i64) -> Result<(), AppError> {
+        let state = self.state.lock().unwrap();
+        if !state.textures.contains_key(&id) {
+            return Err(AppError::UnknownTexture(id));
+        }
+        match &state.engine {
+            Some(engine) => engine.schedule_frame(),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces a fixed texture with the next frame and schedules a frame.
+    pub fn update_texture(&self, id: i64, texture: wgpu::Texture) -> Result<(), AppError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let Some(entry) = state.textures.get_mut(&id) else {
+                return Err(AppError::UnknownTexture(id));
+            };
+            *entry = ExternalTexture::Texture(texture);
+        }
+        self.mark_frame_available(id)
+    }
+
+    /// The texture to draw for `id` at the given size, asking the producer if there is one.
+    fn resolve(&self, id: i64, width: u32, height: u32) -> Option<wgpu::Texture> {
+        let mut state = self.state.lock().unwrap();
+        match state.textures.get_mut(&id)? {
+            ExternalTexture::Texture(texture) => Some(texture.clone()),
+            ExternalTexture::Producer(producer) => producer(width, height),
+        }
+    }
+
+    /// Adds the [`TEXTURE_VIEW_TYPE`] factory that draws these textures to `platform_views`.
+    pub(crate) fn register_view_factory(&self, platform_views: &PlatformViewRegistry) {
+        platform_views.register_factory(
+            TEXTURE_VIEW_TYPE,
+            TextureViewFactory {
+                registry: self.clone(),
+                views: HashMap::new(),
+            },
+        );
+    }
+
+    /// Connects the registry to a running engine, to schedule frames for new textures.
+    pub(crate) fn bind(&self, engine: FlutterEngine, procs: &FlutterEngineProcTable) {
+        let mut state = self.state.lock().unwrap();
+        info!(
+            "Texture registry bound with {} textures",
+            state.textures.len()
+        );
+        state.engine = Some(EngineBinding {
+            engine,
+            schedule_frame: procs.ScheduleFrame,
+        });
+    }
+
+    /// Detaches the registry before the engine shuts down. Registered textures are kept.
+    pub(crate) fn unbind(&self) {
+        self.state.lock().unwrap().engine = None;
+    }
+}
+
+/// Draws registered textures as platform views of type [`TEXTURE_VIEW_TYPE`].
+struct TextureViewFactory {
+    registry: TextureRegistry,
+    /// The texture id each view shows.
+    views: HashMap<i64, i64>,
+}
+
+impl PlatformViewFactory for TextureViewFactory {
+    fn create(&mut self, id: i64, params: &EncodableValue) -> Result<(), String> {
+        let Some(texture_id) = params.get("textureId").and_then(EncodableValue::as_i64) else {
+            return Err(format!("{} expects a textureId", TEXTURE_VIEW_TYPE));
+        };
+        if !self
+            .registry
+            .state
+            .lock()
+            .unwrap()
+            .textures
+            .contains_key(&texture_id)
+        {
+            return Err(AppError::UnknownTexture(texture_id).to_string());
+        }
+        self.views.insert(id, texture_id);
+        Ok(())
+    }
+
+    fn render(
+        &mut self,
+        id: i64,
+        size: PhysicalSize<u32>,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+    ) -> Option<wgpu::Texture> {
+        self.registry
+            .resolve(*self.views.get(&id)?, size.width, size.height)
+    }
+
+    fn dispose(&mut self, id: i64) {
+        self.views.remove(&id);
+    }
+}
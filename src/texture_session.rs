@@ -0,0 +1,220 @@
+use tracing::error;
+use winit::dpi::PhysicalPosition;
+
+use crate::application::{AppConfig, AppError, AppWindowSession, EngineRestarter, GPUContext};
+use crate::asset_watcher::AssetWatcher;
+use crate::backing_store_pool::BackingStorePool;
+use crate::composition::{Compositor, IMPLICIT_VIEW_ID};
+use crate::frame_stats::FrameStatsStream;
+use crate::input::PointerButton;
+use crate::platform_views::PlatformViewRegistry;
+use crate::semantics::Semantics;
+use crate::texture_registry::TextureRegistry;
+use crate::vm_service::VmService;
+
+/// Maps a position in the host's coordinate space (for example a hit point on a
+/// 3D panel) to physical pixels of the Flutter texture.
+/// Returning `None` means the pointer is outside the Flutter content.
+pub type InputTransform = Box<dyn Fn(PhysicalPosition<f64>) -> Option<PhysicalPosition<f64>>>;
+
+/// Runs a Flutter engine that renders into a `wgpu::Texture` owned by a host
+/// renderer instead of a window.
+///
+/// The texture must come from the same device as `gpu_context`, which is shared
+/// with the engine. It is rewritten every time the engine presents a frame, so
+/// the host can sample it as an overlay or as a panel in a 3D scene.
+///
+/// The engine's platform tasks run in [`TextureSession::run_platform_tasks`],
+/// which the host calls from its own loop on the thread that created the session.
+pub struct TextureSession {
+    session: Box<AppWindowSession>,
+    pixel_ratio: f64,
+    input_transform: InputTransform,
+    texture_registry: TextureRegistry,
+    platform_views: PlatformViewRegistry,
+    semantics: Semantics,
+    vm_service: VmService,
+    restarter: EngineRestarter,
+    _asset_watcher: Option<AssetWatcher>,
+}
+
+impl TextureSession {
+    /// Starts the engine. `texture` needs `RENDER_ATTACHMENT` usage, and if its
+    /// format is sRGB the non-sRGB variant must be listed in its `view_formats`.
+    pub fn new(
+        config: AppConfig,
+        gpu_context: GPUContext,
+        texture: wgpu::Texture,
+        pixel_ratio: f64,
+    ) -> Result<Self, AppError> {
+        gpu_context.validate_for_flutter()?;
+        let compositor = Compositor::new_with_texture(
+            gpu_context.instance,
+            gpu_context.device,
+            gpu_context.queue,
+            texture,
+        );
+        let texture_registry = TextureRegistry::new();
+        let platform_views = PlatformViewRegistry::new();
+        texture_registry.register_view_factory(&platform_views);
+        let semantics = Semantics::new();
+        let vm_service = VmService::new(&config);
+        let restarter = EngineRestarter::default();
+        let asset_watcher = AssetWatcher::start(&config, vm_service.clone(), restarter.clone())?;
+        // Boxed before initialization since the engine keeps a pointer to it.
+        let mut session = Box::new(AppWindowSession::with_compositor(
+            config,
+            None,
+            compositor,
+            texture_registry.clone(),
+            platform_views.clone(),
+            semantics.clone(),
+            vm_service.clone(),
+        )?);
+        session.initialize()?;
+
+        let texture_session = Self {
+            session,
+            pixel_ratio,
+            input_transform: Box::new(Some),
+            texture_registry,
+            platform_views,
+            semantics,
+            vm_service,
+            restarter,
+            _asset_watcher: asset_watcher,
+        };
+        texture_session.send_window_metrics_event()?;
+        Ok(texture_session)
+    }
+
+    /// The texture Flutter is composited into.
+    pub fn texture(&self) -> wgpu::Texture {
+        self.session
+            .with_view_compositor(IMPLICIT_VIEW_ID, |compositor| {
+                compositor.target_texture().cloned()
+            })
+            .flatten()
+            .expect("texture session compositor always targets a texture")
+    }
+
+    /// Textures that the Flutter UI can show as platform views, see [`TextureRegistry`].
+    pub fn texture_registry(&self) -> TextureRegistry {
+        self.texture_registry.clone()
+    }
+
+    /// The platform view factories. Views are only created while platform tasks
+    /// run, so factories registered right after [`TextureSession::new`] are in time.
+    pub fn platform_views(&self) -> PlatformViewRegistry {
+        self.platform_views.clone()
+    }
+
+    /// The semantics tree of the Flutter UI, in pixels of the texture.
+    /// Call [`Semantics::set_enabled`] to have the engine build it.
+    pub fn semantics(&self) -> Semantics {
+        self.semantics.clone()
+    }
+
+    /// The Dart VM service of a debug engine, for hot reload and hot restart.
+    pub fn vm_service(&self) -> VmService {
+        self.vm_service.clone()
+    }
+
+    /// The textures the engine reuses for its backing stores, e.g. to trim
+    /// them when the host is low on memory.
+    pub fn backing_store_pool(&self) -> BackingStorePool {
+        self.session.backing_store_pool()
+    }
+
+    /// The timing of the frames composited into the texture.
+    pub fn frame_stats(&self) -> FrameStatsStream {
+        self.session.frame_stats()
+    }
+
+    /// Sends the settings of the config's [`SystemSettingsProvider`] again, for
+    /// example after the host switched to dark mode.
+    ///
+    /// [`SystemSettingsProvider`]: crate::settings::SystemSettingsProvider
+    pub fn update_system_settings(&self) -> Result<(), AppError> {
+        self.session.send_system_settings(None)
+    }
+
+    /// Shuts the engine down and starts it again in the same texture, keeping
+    /// the GPU context. Textures and platform view factories stay registered.
+    pub fn restart_engine(&mut self) -> Result<(), AppError> {
+        self.session.restart_engine()?;
+        self.send_window_metrics_event()
+    }
+
+    /// Restarts the engine the next time platform tasks run, from any thread.
+    pub fn restarter(&self) -> EngineRestarter {
+        self.restarter.clone()
+    }
+
+    /// Runs the engine tasks that are due on the host thread, such as platform
+    /// messages, after a restart requested through [`TextureSession::restarter`].
+    pub fn run_platform_tasks(&mut self) {
+        if self.restarter.take_request() {
+            if let Err(error) = self.restart_engine() {
+                error!("Failed to restart engine: {}", error);
+            }
+        }
+        self.session.run_platform_tasks();
+    }
+
+    /// Switches to a new texture, typically with a new size, and relayouts Flutter for it.
+    pub fn set_texture(&mut self, texture: wgpu::Texture) -> Result<(), AppError> {
+        self.session
+            .with_view_compositor(IMPLICIT_VIEW_ID, |compositor| {
+                compositor.set_target_texture(texture)
+            });
+        self.send_window_metrics_event()
+    }
+
+    pub fn set_pixel_ratio(&mut self, pixel_ratio: f64) -> Result<(), AppError> {
+        self.pixel_ratio = pixel_ratio;
+        self.send_window_metrics_event()
+    }
+
+    /// Sets how host pointer positions map onto the texture. Defaults to the identity.
+    pub fn set_input_transform(
+        &mut self,
+        transform: impl Fn(PhysicalPosition<f64>) -> Option<PhysicalPosition<f64>> + 'static,
+    ) {
+        self.input_transform = Box::new(transform);
+    }
+
+    /// Forwards a pointer move, given in host coordinates.
+    pub fn pointer_moved(&mut self, position: PhysicalPosition<f64>) {
+        match (self.input_transform)(position) {
+            Some(position) => self.session.pointer_moved(IMPLICIT_VIEW_ID, position),
+            None => self.session.pointer_left(IMPLICIT_VIEW_ID),
+        }
+    }
+
+    /// Forwards a button press or release at the last forwarded position.
+    pub fn pointer_button(&mut self, button: PointerButton, pressed: bool) {
+        self.session
+            .pointer_button(IMPLICIT_VIEW_ID, button, pressed);
+    }
+
+    /// Tells Flutter the pointer left the texture.
+    pub fn pointer_left(&mut self) {
+        self.session.pointer_left(IMPLICIT_VIEW_ID);
+    }
+
+    /// Recomposites the last frame presented by the engine into the texture.
+    pub fn render(&mut self) {
+        self.session
+            .with_view_compositor(IMPLICIT_VIEW_ID, |compositor| compositor.render());
+    }
+
+    fn send_window_metrics_event(&self) -> Result<(), AppError> {
+        let size = self
+            .session
+            .with_view_compositor(IMPLICIT_VIEW_ID, |compositor| compositor.size())
+            .unwrap_or_default();
+        self.session
+            .send_window_metrics_event(IMPLICIT_VIEW_ID, size, self.pixel_ratio)
+    }
+}
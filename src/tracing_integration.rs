@@ -1,62 +1,245 @@
-// use std::env;
-// use std::sync::Once;
-// use tracing::{debug, error, info, warn};
-// use tracing_subscriber::fmt::format::Format;
-// use tracing_subscriber::registry::LookupSpan;
-// use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
-
-// static INIT: Once = Once::new();
-// static FLUTTER_RUST_EMBEDDER_LOG_ENV_VAR: &str = "FLUTTER_RUST_EMBEDDER_LOG";
-// static FLUTTER_RUST_EMBEDDER_PROFILE_ENV_VAR: &str = "FLUTTER_RUST_EMBEDDER_PROFILE";
-
-// /// Initialize tracing for the library
-// ///
-// /// This should be called early in your library's execution.
-// /// It will only initialize tracing if:
-// /// 1. The environment variable FLUTTER_RUST_EMBEDDER_LOG is set
-// /// 2. Tracing hasn't already been initialized by this function
-// ///
-// /// Example environment variable values:
-// /// - FLUTTER_RUST_EMBEDDER_LOG=debug (sets the log level for your crate)
-// /// - FLUTTER_RUST_EMBEDDER_LOG=my_crate=debug (explicit namespace)
-// /// - FLUTTER_RUST_EMBEDDER_LOG=my_crate=debug,other_crate=warn (multiple directives)
-// pub fn init_tracing() {
-//     // INIT.call_once(|| {
-//     //     // initialize the env filter for the fmt subscriber
-//     //     let flutter_fmt_env_var = env::var(FLUTTER_RUST_EMBEDDER_LOG_ENV_VAR).ok();
-
-//     //     let flutter_fmt_env_filter = flutter_fmt_env_var.and_then(|env_var_str| {
-//     //         // Try to parse the environment variable as an EnvFilter
-//     //         EnvFilter::try_new(env_var_str).ok()
-//     //     });
-
-//     //     // if flutter env filter is not set, use the default
-//     //     let fmt_env_filter =
-//     //         flutter_fmt_env_filter.unwrap_or_else(|| EnvFilter::from_default_env());
-
-//     //     let flutter_profile_env_var = env::var(FLUTTER_RUST_EMBEDDER_PROFILE_ENV_VAR).ok();
-
-//     //     let flutter_profile_env_filter = flutter_profile_env_var.and_then(|env_var_str| {
-//     //         // Try to parse the environment variable as an EnvFilter
-//     //         EnvFilter::try_new(env_var_str).ok()
-//     //     });
-
-//     //     let profile_env_filter =
-//     //         flutter_profile_env_filter.unwrap_or_else(|| EnvFilter::new("off"));
-
-//     // let fmt_layer = fmt::layer()
-//     //     .with_writer(std::io::stdout)
-//     //     .event_format(Format::default().with_thread_ids(true))
-//     //     .with_span_events(fmt::format::FmtSpan::FULL);
-
-//     // let subscriber = Registry::default().with(fmt_layer);
-
-//     // let registry = tracing_subscriber::registry();
-//     // registry.register_filter(fmt_env_filter);
-//     // tracing::subscriber::set_global_default(subscriber).unwrap();
-//     // fmt::Subscriber::builder()
-//     //     .with_env_filter(env_filter)
-//     //     .try_init()
-//     //     .ok();
-//     // });
-// }
+//! Opt-in logging and profiling for apps that do not set up `tracing` themselves,
+//! and mirroring of spans into the timeline of the Flutter engine.
+
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr, CString};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use tracing::span::Id;
+use tracing::{info, Event, Subscriber};
+use tracing_perfetto::PerfettoLayer;
+use tracing_subscriber::fmt::format::Format;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Layer, Registry};
+
+use crate::flutter_embedder::FlutterEngineProcTable;
+
+/// Filter directives for log output on stdout, like `debug` or
+/// `flutter_rust_embedder=trace,wgpu=warn`.
+pub const LOG_ENV_VAR: &str = "FLUTTER_RUST_EMBEDDER_LOG";
+/// Filter directives for spans and events recorded into a Perfetto trace.
+pub const PROFILE_ENV_VAR: &str = "FLUTTER_RUST_EMBEDDER_PROFILE";
+/// Where to write the Perfetto trace, see [`TracingOptions::profile_path`].
+pub const PROFILE_PATH_ENV_VAR: &str = "FLUTTER_RUST_EMBEDDER_PROFILE_PATH";
+
+/// The trace file of the subscriber installed by the first [`init_tracing`] call.
+static INIT: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// The trace entry points of the engine library that has running engines.
+static ENGINE_TIMELINE: RwLock<Option<EngineTimeline>> = RwLock::new(None);
+
+/// Span and event names as C strings. The Dart timeline keeps the pointers
+/// until the VM shuts down, so they are never freed.
+static TIMELINE_NAMES: Mutex<BTreeMap<&'static str, &'static CStr>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone, Copy)]
+struct EngineTimeline {
+    /// The engine library the entry points belong to.
+    library: usize,
+    begin: Option<unsafe extern "C" fn(*const c_char)>,
+    end: Option<unsafe extern "C" fn(*const c_char)>,
+    instant: Option<unsafe extern "C" fn(*const c_char)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TracingOptions {
+    /// The Perfetto trace file. Defaults to `$FLUTTER_RUST_EMBEDDER_PROFILE_PATH`,
+    /// or `trace-<date>_<time>.perfetto-trace` next to the executable.
+    pub profile_path: Option<PathBuf>,
+}
+
+impl TracingOptions {
+    fn profile_path(&self) -> PathBuf {
+        if let Some(path) = &self.profile_path {
+            return path.clone();
+        }
+        if let Some(path) = std::env::var_os(PROFILE_PATH_ENV_VAR) {
+            return PathBuf::from(path);
+        }
+        let time = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+        let file_name = format!("trace-{}.perfetto-trace", time);
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(&file_name)))
+            .unwrap_or_else(|| PathBuf::from(file_name))
+    }
+}
+
+/// Installs a global subscriber with the layers of [`layers`], unless neither
+/// `FLUTTER_RUST_EMBEDDER_LOG` nor `FLUTTER_RUST_EMBEDDER_PROFILE` is set.
+/// Returns the Perfetto trace file, if profiling.
+///
+/// Only the first call does anything, and a subscriber the host installed
+/// before is kept, in which case nothing is recorded by this crate's layers.
+pub fn init_tracing() -> Option<PathBuf> {
+    init_tracing_with(TracingOptions::default())
+}
+
+/// Like [`init_tracing`], with the trace written where `options` says.
+pub fn init_tracing_with(options: TracingOptions) -> Option<PathBuf> {
+    INIT.get_or_init(|| {
+        let (layers, profile_path) = build_layers(&options);
+        if layers.is_empty() {
+            return None;
+        }
+        let subscriber = Registry::default().with(layers);
+        if tracing::subscriber::set_global_default(subscriber).is_err() {
+            // The host's subscriber stays in charge; it can add `layers()` itself.
+            eprintln!("A tracing subscriber is already installed, not installing another");
+            return None;
+        }
+        if let Some(path) = &profile_path {
+            info!("Recording Perfetto trace to {:?}", path);
+        }
+        profile_path
+    })
+    .clone()
+}
+
+/// The layers configured by the environment variables, for hosts that build
+/// their own subscriber, e.g. `Registry::default().with(layers(&options)).with(host_layer)`.
+pub fn layers<S>(options: &TracingOptions) -> Vec<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    build_layers(options).0
+}
+
+fn build_layers<S>(
+    options: &TracingOptions,
+) -> (Vec<Box<dyn Layer<S> + Send + Sync>>, Option<PathBuf>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let mut layers: Vec<Box<dyn Layer<S> + Send + Sync>> = Vec::new();
+    if let Some(directives) = env_directives(LOG_ENV_VAR) {
+        let fmt_layer = fmt::layer()
+            .with_writer(std::io::stdout)
+            .event_format(Format::default().with_thread_ids(true))
+            .with_filter(EnvFilter::new(&directives));
+        layers.push(Box::new(fmt_layer));
+    }
+
+    let mut profile_path = None;
+    if let Some(directives) = env_directives(PROFILE_ENV_VAR) {
+        layers.push(Box::new(
+            EngineTimelineLayer.with_filter(EnvFilter::new(&directives)),
+        ));
+        let path = options.profile_path();
+        match std::fs::File::create(&path) {
+            Ok(file) => {
+                let perfetto_layer = PerfettoLayer::new(Mutex::new(file))
+                    .with_debug_annotations(true)
+                    .with_filter(EnvFilter::new(&directives));
+                layers.push(Box::new(perfetto_layer));
+                profile_path = Some(path);
+            }
+            // No subscriber exists yet to report this through.
+            Err(error) => eprintln!("Failed to create trace file {:?}: {}", path, error),
+        }
+    }
+    (layers, profile_path)
+}
+
+/// The filter directives in `var`, if they parse. Invalid ones are reported on
+/// stderr, since there is no subscriber to log them to yet. Each layer gets its
+/// own `EnvFilter` built from them.
+fn env_directives(var: &str) -> Option<String> {
+    let directives = std::env::var(var).ok()?;
+    match EnvFilter::try_new(&directives) {
+        Ok(_) => Some(directives),
+        Err(error) => {
+            eprintln!("Ignoring invalid {}={}: {}", var, directives, error);
+            None
+        }
+    }
+}
+
+/// Mirrors spans into the timeline of the running Flutter engine, as duration
+/// events named after the span, and events as instant events. DevTools shows
+/// them next to the engine's events, and so does the trace of
+/// [`AppConfig::engine_trace_file`](crate::application::AppConfig::engine_trace_file).
+///
+/// [`init_tracing`] adds it when profiling. Without a running engine, it
+/// does nothing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EngineTimelineLayer;
+
+impl<S> Layer<S> for EngineTimelineLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            mirror(|timeline| timeline.begin, span.name());
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            mirror(|timeline| timeline.end, span.name());
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        mirror(|timeline| timeline.instant, event.metadata().name());
+    }
+}
+
+/// Calls the trace entry point `select` picks with `name`, if an engine runs.
+fn mirror(
+    select: impl FnOnce(&EngineTimeline) -> Option<unsafe extern "C" fn(*const c_char)>,
+    name: &'static str,
+) {
+    // Held during the call, so the library is not unloaded meanwhile.
+    let timeline = ENGINE_TIMELINE.read().unwrap();
+    let Some(trace) = timeline.as_ref().and_then(select) else {
+        return;
+    };
+    let Some(name) = timeline_name(name) else {
+        return;
+    };
+    unsafe { trace(name.as_ptr()) };
+}
+
+fn timeline_name(name: &'static str) -> Option<&'static CStr> {
+    let mut names = TIMELINE_NAMES.lock().unwrap();
+    if let Some(name) = names.get(name) {
+        return Some(name);
+    }
+    let c_name: &'static CStr = Box::leak(CString::new(name).ok()?.into_boxed_c_str());
+    names.insert(name, c_name);
+    Some(c_name)
+}
+
+/// Starts mirroring into the timeline of the engines of `library`, once its
+/// first engine runs.
+///
+/// The engine writes its trace to its own file, on its own clock, and the
+/// Rust trace is not merged into it. So this records the engine clock
+/// (`FlutterEngineGetCurrentTime`) in a `clock_sync` event of the Rust trace,
+/// also mirrored as an instant of the same name into the engine's. The
+/// difference between its `engine_time_nanos` and its timestamp in the Rust
+/// trace is the offset between the two traces.
+pub(crate) fn attach_engine_timeline(library: usize, procs: &FlutterEngineProcTable) {
+    *ENGINE_TIMELINE.write().unwrap() = Some(EngineTimeline {
+        library,
+        begin: procs.TraceEventDurationBegin,
+        end: procs.TraceEventDurationEnd,
+        instant: procs.TraceEventInstant,
+    });
+    if let Some(get_current_time) = procs.GetCurrentTime {
+        let engine_time_nanos = unsafe { get_current_time() };
+        info!(name: "clock_sync", engine_time_nanos, "Engine timeline attached");
+    }
+}
+
+/// Stops mirroring into the engines of `library`, once its last engine stopped.
+pub(crate) fn detach_engine_timeline(library: usize) {
+    let mut timeline = ENGINE_TIMELINE.write().unwrap();
+    if timeline.is_some_and(|timeline| timeline.library == library) {
+        *timeline = None;
+    }
+}
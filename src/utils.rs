@@ -1,3 +1,21 @@
+use std::panic::AssertUnwindSafe;
+
+use tracing::error;
+
 pub fn as_void_ptr<T>(mut_ref: &mut T) -> *mut ::core::ffi::c_void {
     mut_ref as *mut T as *mut ::core::ffi::c_void
 }
+
+/// Runs the body of a callback called by the engine. A panic unwinding into
+/// C would abort the process, so it is logged and `fallback` returned instead.
+pub(crate) fn catch_callback_panic<R>(callback: &str, fallback: R, f: impl FnOnce() -> R) -> R {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        error!("Panic in {}: {}", callback, message);
+        fallback
+    })
+}
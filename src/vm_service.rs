@@ -0,0 +1,412 @@
+//! The Dart VM service of debug builds: discovering its URI from the engine
+//! log, and hot reload and hot restart through its JSON-RPC protocol.
+
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tracing::{debug, error, info};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::application::{AppConfig, AppError};
+use crate::json_codec::JsonValue;
+
+/// Finds the URI in the line the engine logs once the VM service listens, like
+/// `The Dart VM service is listening on http://127.0.0.1:40247/vn0dSuwx4bU=/`.
+pub(crate) fn parse_service_uri(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once("listening on ")?;
+    rest.split_whitespace()
+        .next()
+        .filter(|uri| uri.starts_with("http://") || uri.starts_with("https://"))
+}
+
+/// The VM service of an engine, which only debug builds of the engine start.
+///
+/// Reloads and restarts run the `kernel_blob.bin` in the assets, so rebuild
+/// it first, e.g. with `flutter build bundle`. The calls block until the
+/// VM service answered, so make them from a tool thread rather than the
+/// thread that runs the app.
+#[derive(Clone, Debug)]
+pub struct VmService {
+    uri: Arc<Mutex<Option<String>>>,
+    flutter_assets: PathBuf,
+    info_file: Option<PathBuf>,
+}
+
+impl VmService {
+    pub(crate) fn new(config: &AppConfig) -> Self {
+        Self {
+            uri: Arc::new(Mutex::new(None)),
+            flutter_assets: config.asset_dir.join("flutter_assets"),
+            info_file: config.vm_service_info_file.clone(),
+        }
+    }
+
+    /// Talks to the VM service at `uri` of an engine that runs the assets in
+    /// `asset_dir`, such as one started by another process, or a mock server
+    /// in tests.
+    pub fn with_uri(uri: &str, asset_dir: impl AsRef<Path>) -> Self {
+        Self {
+            uri: Arc::new(Mutex::new(Some(uri.to_string()))),
+            flutter_assets: asset_dir.as_ref().join("flutter_assets"),
+            info_file: None,
+        }
+    }
+
+    /// The HTTP URI of the VM service, once the engine reported it.
+    pub fn uri(&self) -> Option<String> {
+        self.uri.lock().unwrap().clone()
+    }
+
+    /// Records the URI reported by the engine and writes the service info
+    /// file, if one is configured.
+    pub(crate) fn set_uri(&self, uri: &str) {
+        info!("Dart VM service listening on {}", uri);
+        *self.uri.lock().unwrap() = Some(uri.to_string());
+        if let Some(info_file) = &self.info_file {
+            let info = JsonValue::object([("uri", uri.into())]);
+            if let Err(error) = std::fs::write(info_file, info.to_string()) {
+                error!("Failed to write {:?}: {}", info_file, error);
+            }
+        }
+    }
+
+    /// Loads the changed libraries of `kernel_blob.bin` into the running
+    /// isolates and rebuilds their widgets, keeping the app state.
+    pub fn hot_reload(&self) -> Result<(), AppError> {
+        let mut connection = self.connect()?;
+        let kernel = file_uri(&self.flutter_assets.join("kernel_blob.bin"));
+        for (_, isolate) in connection.flutter_views()? {
+            let report = connection.call(
+                "reloadSources",
+                JsonValue::object([
+                    ("isolateId", isolate.as_str().into()),
+                    ("rootLibUri", kernel.as_str().into()),
+                ]),
+            )?;
+            if report.get("success") != Some(&JsonValue::Bool(true)) {
+                error!("Hot reload of {} rejected: {}", isolate, report);
+                return Err(AppError::VmService(format!(
+                    "hot reload rejected: {}",
+                    report
+                )));
+            }
+            connection.call(
+                "ext.flutter.reassemble",
+                JsonValue::object([("isolateId", isolate.as_str().into())]),
+            )?;
+        }
+        info!("Hot reload done");
+        Ok(())
+    }
+
+    /// Drops the assets with the keys `asset_keys`, such as `assets/logo.png`,
+    /// from Flutter's caches, so they are loaded from disk on the next reload.
+    pub fn evict_assets(&self, asset_keys: &[String]) -> Result<(), AppError> {
+        if asset_keys.is_empty() {
+            return Ok(());
+        }
+        let mut connection = self.connect()?;
+        for (_, isolate) in connection.flutter_views()? {
+            for key in asset_keys {
+                connection.call(
+                    "ext.flutter.evict",
+                    JsonValue::object([
+                        ("isolateId", isolate.as_str().into()),
+                        ("value", key.as_str().into()),
+                    ]),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restarts the isolates from `kernel_blob.bin`, losing the app state.
+    pub fn hot_restart(&self) -> Result<(), AppError> {
+        let mut connection = self.connect()?;
+        let kernel = file_uri(&self.flutter_assets.join("kernel_blob.bin"));
+        let assets = file_uri(&self.flutter_assets);
+        for (view, _) in connection.flutter_views()? {
+            connection.call(
+                "_flutter.runInView",
+                JsonValue::object([
+                    ("viewId", view.as_str().into()),
+                    ("mainScript", kernel.as_str().into()),
+                    ("assetDirectory", assets.as_str().into()),
+                ]),
+            )?;
+        }
+        info!("Hot restart done");
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<Connection, AppError> {
+        let Some(uri) = self.uri() else {
+            error!("The Dart VM service is not listening");
+            return Err(AppError::VmService("not listening".to_string()));
+        };
+        Connection::open(&websocket_uri(&uri))
+    }
+}
+
+/// The WebSocket endpoint of a VM service HTTP URI.
+fn websocket_uri(uri: &str) -> String {
+    let uri = if let Some(rest) = uri.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else {
+        format!("ws://{}", uri.strip_prefix("http://").unwrap_or(uri))
+    };
+    if uri.ends_with('/') {
+        uri + "ws"
+    } else {
+        uri + "/ws"
+    }
+}
+
+fn file_uri(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let path = path.to_string_lossy().replace('\\', "/");
+    // Windows paths are canonicalized to `\\?\C:\...` and need an empty
+    // authority before the drive letter.
+    let path = path.trim_start_matches("//?/");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+/// A JSON-RPC connection to the VM service.
+struct Connection {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl Connection {
+    fn open(uri: &str) -> Result<Self, AppError> {
+        debug!("Connecting to the Dart VM service at {}", uri);
+        let (socket, _) = tungstenite::connect(uri).map_err(|error| {
+            error!(
+                "Failed to connect to the Dart VM service at {}: {}",
+                uri, error
+            );
+            AppError::VmService(error.to_string())
+        })?;
+        Ok(Self { socket, next_id: 0 })
+    }
+
+    /// Calls `method` and returns its result, skipping the stream events
+    /// that arrive in between.
+    fn call(&mut self, method: &str, params: JsonValue) -> Result<JsonValue, AppError> {
+        self.next_id += 1;
+        let id = self.next_id.to_string();
+        let request = JsonValue::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id.as_str().into()),
+            ("method", method.into()),
+            ("params", params),
+        ]);
+        self.socket
+            .send(Message::Text(request.to_string().into()))
+            .map_err(|error| {
+                error!(
+                    "Failed to send {} to the Dart VM service: {}",
+                    method, error
+                );
+                AppError::VmService(error.to_string())
+            })?;
+        loop {
+            let message = self.socket.read().map_err(|error| {
+                error!("Failed to read the reply to {}: {}", method, error);
+                AppError::VmService(error.to_string())
+            })?;
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => {
+                    error!(
+                        "The Dart VM service closed the connection during {}",
+                        method
+                    );
+                    return Err(AppError::VmService("connection closed".to_string()));
+                }
+                _ => continue,
+            };
+            let Ok(response) = JsonValue::parse(text.as_str()) else {
+                debug!("Ignoring invalid VM service message {}", text.as_str());
+                continue;
+            };
+            if response.get("id").and_then(JsonValue::as_str) != Some(id.as_str()) {
+                continue;
+            }
+            if let Some(rpc_error) = response.get("error") {
+                error!("{} failed: {}", method, rpc_error);
+                return Err(AppError::VmService(format!(
+                    "{} failed: {}",
+                    method, rpc_error
+                )));
+            }
+            return Ok(response.get("result").cloned().unwrap_or(JsonValue::Null));
+        }
+    }
+
+    /// The ids of the Flutter views, each with its isolate, one view per isolate.
+    fn flutter_views(&mut self) -> Result<Vec<(String, String)>, AppError> {
+        let result = self.call("_flutter.listViews", JsonValue::object([]))?;
+        let mut views: Vec<(String, String)> = Vec::new();
+        if let Some(JsonValue::Array(entries)) = result.get("views") {
+            for view in entries {
+                let id = view.get("id").and_then(JsonValue::as_str);
+                let isolate = view
+                    .get("isolate")
+                    .and_then(|isolate| isolate.get("id"))
+                    .and_then(JsonValue::as_str);
+                if let (Some(id), Some(isolate)) = (id, isolate) {
+                    if !views.iter().any(|(_, known)| known == isolate) {
+                        views.push((id.to_string(), isolate.to_string()));
+                    }
+                }
+            }
+        }
+        if views.is_empty() {
+            error!("The Dart VM service reported no Flutter views");
+            return Err(AppError::VmService("no Flutter views".to_string()));
+        }
+        Ok(views)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    use super::*;
+
+    #[test]
+    fn parses_service_uris() {
+        assert_eq!(
+            parse_service_uri(
+                "The Dart VM service is listening on http://127.0.0.1:40247/vn0dSuwx4bU=/"
+            ),
+            Some("http://127.0.0.1:40247/vn0dSuwx4bU=/")
+        );
+        assert_eq!(
+            parse_service_uri("The Dart VM service is listening on http://127.0.0.1:40247/"),
+            Some("http://127.0.0.1:40247/")
+        );
+        assert_eq!(
+            parse_service_uri("The Dart VM service is listening on port 40247"),
+            None
+        );
+        assert_eq!(parse_service_uri("flutter: listening on"), None);
+    }
+
+    #[test]
+    fn maps_service_uris_to_websockets() {
+        assert_eq!(
+            websocket_uri("http://127.0.0.1:40247/vn0dSuwx4bU=/"),
+            "ws://127.0.0.1:40247/vn0dSuwx4bU=/ws"
+        );
+        assert_eq!(
+            websocket_uri("http://127.0.0.1:40247"),
+            "ws://127.0.0.1:40247/ws"
+        );
+        assert_eq!(
+            websocket_uri("https://localhost:40247/abc=/"),
+            "wss://localhost:40247/abc=/ws"
+        );
+    }
+
+    /// Answers the VM service calls of `connections` connections like a
+    /// running app with one view, and returns the calls it got.
+    fn serve(listener: TcpListener, connections: usize) -> JoinHandle<Vec<(String, JsonValue)>> {
+        thread::spawn(move || {
+            let mut calls = Vec::new();
+            for stream in listener.incoming().take(connections) {
+                let mut socket = tungstenite::accept(stream.unwrap()).unwrap();
+                while let Ok(message) = socket.read() {
+                    let Message::Text(text) = message else {
+                        continue;
+                    };
+                    let request = JsonValue::parse(text.as_str()).unwrap();
+                    let method = request.get("method").and_then(JsonValue::as_str).unwrap();
+                    let result = match method {
+                        "_flutter.listViews" => JsonValue::parse(
+                            r#"{"views":[{"id":"_flutterView/0x1","isolate":{"id":"isolates/1"}}]}"#,
+                        )
+                        .unwrap(),
+                        "reloadSources" => JsonValue::object([("success", JsonValue::Bool(true))]),
+                        _ => JsonValue::object([]),
+                    };
+                    // Stream events arrive in between replies.
+                    let event = JsonValue::object([
+                        ("jsonrpc", "2.0".into()),
+                        ("method", "streamNotify".into()),
+                    ]);
+                    socket
+                        .send(Message::Text(event.to_string().into()))
+                        .unwrap();
+                    let reply = JsonValue::object([
+                        ("jsonrpc", "2.0".into()),
+                        ("id", request.get("id").cloned().unwrap()),
+                        ("result", result),
+                    ]);
+                    socket
+                        .send(Message::Text(reply.to_string().into()))
+                        .unwrap();
+                    calls.push((method.to_string(), request.get("params").cloned().unwrap()));
+                }
+            }
+            calls
+        })
+    }
+
+    #[test]
+    fn reloads_and_restarts_through_the_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/vn0dSuwx4bU=/", listener.local_addr().unwrap());
+        let server = serve(listener, 2);
+
+        let vm_service = VmService::with_uri(&uri, std::env::temp_dir());
+        vm_service.hot_reload().unwrap();
+        vm_service.hot_restart().unwrap();
+        let calls = server.join().unwrap();
+
+        let methods: Vec<&str> = calls.iter().map(|(method, _)| method.as_str()).collect();
+        assert_eq!(
+            methods,
+            [
+                "_flutter.listViews",
+                "reloadSources",
+                "ext.flutter.reassemble",
+                "_flutter.listViews",
+                "_flutter.runInView",
+            ]
+        );
+        let isolate = Some(&JsonValue::from("isolates/1"));
+        assert_eq!(calls[1].1.get("isolateId"), isolate);
+        assert!(calls[1]
+            .1
+            .get("rootLibUri")
+            .and_then(JsonValue::as_str)
+            .is_some_and(
+                |uri| uri.starts_with("file://") && uri.ends_with("flutter_assets/kernel_blob.bin")
+            ));
+        assert_eq!(calls[2].1.get("isolateId"), isolate);
+        assert_eq!(
+            calls[4].1.get("viewId"),
+            Some(&JsonValue::from("_flutterView/0x1"))
+        );
+    }
+
+    #[test]
+    fn fails_without_a_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        assert!(VmService::with_uri(&uri, std::env::temp_dir())
+            .hot_reload()
+            .is_err());
+    }
+}